@@ -0,0 +1,165 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    future::{ready, Future, Ready},
+    io::BufReader,
+    pin::Pin,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::AUTHORIZATION,
+    web::Data,
+    Error, HttpMessage, HttpResponse,
+};
+use serde::Deserialize;
+
+use crate::AppState;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scope {
+    #[serde(rename = "read")]
+    Read,
+    #[serde(rename = "points:write")]
+    PointsWrite,
+    #[serde(rename = "users:write")]
+    UsersWrite,
+    #[serde(rename = "verify:write")]
+    VerifyWrite,
+    #[serde(rename = "events:write")]
+    EventsWrite,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Scope::Read => "read",
+            Scope::PointsWrite => "points:write",
+            Scope::UsersWrite => "users:write",
+            Scope::VerifyWrite => "verify:write",
+            Scope::EventsWrite => "events:write",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// A caller of the API, e.g. the discord bot or a roblox game server
+#[derive(Deserialize, Clone, Debug)]
+pub struct ApiKey {
+    pub name: String,
+    key: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl ApiKey {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+pub struct ApiKeyStore {
+    keys: HashMap<String, ApiKey>,
+}
+
+impl ApiKeyStore {
+    pub fn new(keys: Vec<ApiKey>) -> Self {
+        ApiKeyStore {
+            keys: keys.into_iter().map(|k| (k.key.clone(), k)).collect(),
+        }
+    }
+
+    /// Reads a JSON array of `{ "name", "key", "scopes" }` objects
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let keys: Vec<ApiKey> = serde_json::from_reader(reader)?;
+
+        Ok(ApiKeyStore::new(keys))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&ApiKey> {
+        self.keys.get(key)
+    }
+}
+
+/// Accepts either `Authorization: Bearer <key>` or `X-Api-Key: <key>`
+fn extract_key(req: &ServiceRequest) -> Option<String> {
+    let headers = req.headers();
+    if let Some(value) = headers.get(AUTHORIZATION) {
+        let value = value.to_str().ok()?;
+        return value.strip_prefix("Bearer ").map(|k| k.trim().to_string());
+    }
+
+    headers
+        .get("X-Api-Key")
+        .and_then(|value| value.to_str().ok())
+        .map(|k| k.trim().to_string())
+}
+
+/// Route middleware that rejects callers without an API key carrying the given scope.
+/// The matched `ApiKey` is stored in the request extensions, handlers read it with `ReqData<ApiKey>`
+pub struct RequireScope(pub Scope);
+
+impl<S, B> Transform<S, ServiceRequest> for RequireScope
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RequireScopeMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireScopeMiddleware {
+            service,
+            scope: self.0,
+        }))
+    }
+}
+
+pub struct RequireScopeMiddleware<S> {
+    service: S,
+    scope: Scope,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireScopeMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let api_key = match (req.app_data::<Data<AppState>>(), extract_key(&req)) {
+            (Some(app_state), Some(key)) => app_state.api_keys.get(&key).cloned(),
+            _ => None,
+        };
+
+        let rejection = match &api_key {
+            None => Some(HttpResponse::Unauthorized().body("Missing or unknown API key")),
+            Some(api_key) if !api_key.has_scope(self.scope) => Some(
+                HttpResponse::Forbidden().body(format!("API key lacks the {} scope", self.scope)),
+            ),
+            Some(_) => None,
+        };
+
+        if let Some(response) = rejection {
+            return Box::pin(async move { Ok(req.into_response(response).map_into_right_body()) });
+        }
+
+        if let Some(api_key) = api_key {
+            req.extensions_mut().insert(api_key);
+        }
+
+        let response = self.service.call(req);
+        Box::pin(async move { Ok(response.await?.map_into_left_body()) })
+    }
+}
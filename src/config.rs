@@ -0,0 +1,489 @@
+use std::{env, fs, path::Path};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use toml::Value;
+
+use crate::definitions::users::Division;
+
+/// Prefix for environment variables that override config values,
+/// e.g. `WAVE_SERVER_PORT=8081` overrides `port` in the `[server]` table
+const ENV_PREFIX: &str = "WAVE_";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ServerConfig {
+    #[serde(default = "default_bind_address")]
+    pub bind_address: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RobloxConfig {
+    pub cookie_path: String,
+    #[serde(default = "default_true")]
+    pub validate_cookie: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    Firebase,
+    Sqlite,
+    Memory,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    #[serde(default)]
+    pub firebase_project_id: Option<String>,
+    #[serde(default)]
+    pub firebase_key_path: Option<String>,
+    #[serde(default)]
+    pub sqlite_path: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebhookConfig {
+    pub log: String,
+    pub error: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GroupConfig {
+    pub wij: u64,
+    pub st: u64,
+    pub sable: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LeaderboardConfig {
+    pub refresh_secs: u64,
+    #[serde(default = "default_weekly_refresh_secs")]
+    pub weekly_refresh_secs: u64,
+    #[serde(default = "default_monthly_refresh_secs")]
+    pub monthly_refresh_secs: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct JobsConfig {
+    pub interval_ms: u64,
+    pub verification_timeout_ms: u64,
+    #[serde(default = "default_role_sync_secs")]
+    pub role_sync_secs: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AuthConfig {
+    pub api_keys_path: String,
+    pub signing_secret_path: String,
+    #[serde(default = "default_max_clock_skew")]
+    pub max_clock_skew_secs: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RanksConfig {
+    pub path: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PrestigeConfig {
+    /// Rank at the top of the enlisted ladder that prestige starts from
+    pub rank: String,
+    pub required_points: i32,
+    /// Points a user drops back to after prestiging
+    #[serde(default)]
+    pub reset_points: i32,
+    /// Rank a user is moved to after prestiging, required since staying at the prestige rank
+    /// would leave them at its floor
+    #[serde(default)]
+    pub reset_rank: Option<String>,
+    /// Optional role handed out in another group as a prestige badge
+    #[serde(default)]
+    pub badge_group: Option<u64>,
+    #[serde(default)]
+    pub badge_role_id: Option<u64>,
+}
+
+/// How deductions count towards a user's lifetime `total_points`
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TotalPointsPolicy {
+    /// Only awards count, deductions leave the total alone
+    #[default]
+    PositiveOnly,
+    /// Deductions are subtracted from the total as well
+    Net,
+}
+
+impl TotalPointsPolicy {
+    pub fn lifetime_amount(self, amount: i32) -> i32 {
+        match self {
+            TotalPointsPolicy::PositiveOnly => amount.max(0),
+            TotalPointsPolicy::Net => amount,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct PointsConfig {
+    #[serde(default)]
+    pub total_policy: TotalPointsPolicy,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaPeriod {
+    /// Monday to Sunday, UTC
+    Weekly,
+    Monthly,
+}
+
+/// An activity requirement members have to meet every period
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct QuotaRule {
+    pub name: String,
+    pub period: QuotaPeriod,
+    /// Ranks the rule applies to, every rank when empty
+    #[serde(default)]
+    pub ranks: Vec<String>,
+    /// Divisions (`st`, `sable`) the rule applies to, everyone when empty
+    #[serde(default)]
+    pub divisions: Vec<Division>,
+    #[serde(default)]
+    pub min_events: u32,
+    #[serde(default)]
+    pub min_points: i32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum InactivityAction {
+    /// Only report inactive members
+    #[default]
+    Flag,
+    /// Drop them one rank down the ladder
+    Demote,
+    /// Move them to `reserve_rank`
+    Reserve,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct InactivityConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Weeks without a standing award before a member counts as inactive
+    #[serde(default = "default_inactive_weeks")]
+    pub weeks: u64,
+    #[serde(default)]
+    pub action: InactivityAction,
+    #[serde(default)]
+    pub reserve_rank: Option<String>,
+    /// Ranks that are never touched, e.g. command staff
+    #[serde(default)]
+    pub exempt_ranks: Vec<String>,
+    /// Only post what would happen, without ranking anyone
+    #[serde(default = "default_true")]
+    pub dry_run: bool,
+    #[serde(default = "default_inactivity_check_secs")]
+    pub check_secs: u64,
+}
+
+impl Default for InactivityConfig {
+    fn default() -> Self {
+        InactivityConfig {
+            enabled: false,
+            weeks: default_inactive_weeks(),
+            action: InactivityAction::default(),
+            reserve_rank: None,
+            exempt_ranks: vec![],
+            dry_run: true,
+            check_secs: default_inactivity_check_secs(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub roblox: RobloxConfig,
+    pub storage: StorageConfig,
+    pub webhooks: WebhookConfig,
+    pub groups: GroupConfig,
+    pub leaderboard: LeaderboardConfig,
+    pub jobs: JobsConfig,
+    pub auth: AuthConfig,
+    pub ranks: RanksConfig,
+    pub prestige: PrestigeConfig,
+    #[serde(default)]
+    pub points: PointsConfig,
+    #[serde(default)]
+    pub quotas: Vec<QuotaRule>,
+    #[serde(default)]
+    pub inactivity: InactivityConfig,
+}
+
+fn default_bind_address() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_port() -> u16 {
+    8080
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_clock_skew() -> u64 {
+    300
+}
+
+fn default_role_sync_secs() -> u64 {
+    3600
+}
+
+fn default_weekly_refresh_secs() -> u64 {
+    600
+}
+
+fn default_monthly_refresh_secs() -> u64 {
+    3600
+}
+
+fn default_inactive_weeks() -> u64 {
+    4
+}
+
+fn default_inactivity_check_secs() -> u64 {
+    86400
+}
+
+/// Environment values are untyped. They take the type of the value they replace, so numeric
+/// looking strings stay strings; keys missing from the file get the type a human would read
+fn parse_env_value(raw: &str, existing: Option<&Value>) -> Value {
+    let parsed = match existing {
+        Some(Value::Integer(_)) => raw.parse::<i64>().ok().map(Value::Integer),
+        Some(Value::Float(_)) => raw.parse::<f64>().ok().map(Value::Float),
+        Some(Value::Boolean(_)) => raw.parse::<bool>().ok().map(Value::Boolean),
+        Some(_) => Some(Value::String(raw.to_string())),
+        None => None,
+    };
+    if let Some(value) = parsed {
+        return value;
+    }
+
+    if let Ok(int) = raw.parse::<i64>() {
+        Value::Integer(int)
+    } else if let Ok(boolean) = raw.parse::<bool>() {
+        Value::Boolean(boolean)
+    } else {
+        Value::String(raw.to_string())
+    }
+}
+
+/// Applies every `WAVE_<TABLE>_<KEY>` variable on top of the parsed file
+fn apply_env_overrides(root: &mut Value, vars: impl Iterator<Item = (String, String)>) {
+    let tables = match root.as_table_mut() {
+        Some(tables) => tables,
+        None => return,
+    };
+
+    for (name, raw) in vars {
+        let path = match name.strip_prefix(ENV_PREFIX) {
+            Some(path) => path.to_lowercase(),
+            None => continue,
+        };
+        let (table, key) = match path.split_once('_') {
+            Some(split) => split,
+            None => continue,
+        };
+
+        let entry = tables
+            .entry(table.to_string())
+            .or_insert_with(|| Value::Table(Default::default()));
+        if let Some(table) = entry.as_table_mut() {
+            let value = parse_env_value(&raw, table.get(key));
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+impl Config {
+    /// Reads the file at `WAVE_CONFIG` (default `config.toml`), applies env overrides and validates it
+    pub fn load() -> anyhow::Result<Self> {
+        let path = env::var("WAVE_CONFIG").unwrap_or_else(|_| "config.toml".to_string());
+        Config::from_file(&path)
+    }
+
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("failed to read config {}", path))?;
+        let mut root: Value = toml::from_str(&contents)
+            .with_context(|| format!("failed to parse config {}", path))?;
+
+        apply_env_overrides(
+            &mut root,
+            env::vars().filter(|(name, _)| name != "WAVE_CONFIG"),
+        );
+
+        let config: Config = root
+            .try_into()
+            .with_context(|| format!("invalid config {}", path))?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Catches mistakes that serde can't, reporting every problem at once
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut problems: Vec<String> = vec![];
+
+        for (name, url) in [
+            ("webhooks.log", &self.webhooks.log),
+            ("webhooks.error", &self.webhooks.error),
+        ] {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                problems.push(format!("{} must be an http(s) url", name));
+            }
+        }
+
+        let mut required_files = vec![("ranks.path", Some(&self.ranks.path))];
+        match self.storage.backend {
+            StorageBackend::Firebase => {
+                if self.storage.firebase_project_id.is_none() {
+                    problems.push(
+                        "storage.firebase_project_id is required for the firebase backend"
+                            .to_string(),
+                    );
+                }
+                required_files.push((
+                    "storage.firebase_key_path",
+                    self.storage.firebase_key_path.as_ref(),
+                ));
+            }
+            StorageBackend::Sqlite => {
+                if self.storage.sqlite_path.is_none() {
+                    problems
+                        .push("storage.sqlite_path is required for the sqlite backend".to_string());
+                }
+            }
+            StorageBackend::Memory => {}
+        }
+        problems.extend(missing_files(required_files));
+
+        for (name, id) in [
+            ("groups.wij", self.groups.wij),
+            ("groups.st", self.groups.st),
+            ("groups.sable", self.groups.sable),
+        ] {
+            if id == 0 {
+                problems.push(format!("{} must be a roblox group id", name));
+            }
+        }
+
+        for (name, secs) in [
+            ("leaderboard.refresh_secs", self.leaderboard.refresh_secs),
+            (
+                "leaderboard.weekly_refresh_secs",
+                self.leaderboard.weekly_refresh_secs,
+            ),
+            (
+                "leaderboard.monthly_refresh_secs",
+                self.leaderboard.monthly_refresh_secs,
+            ),
+        ] {
+            if secs == 0 {
+                problems.push(format!("{} must be greater than 0", name));
+            }
+        }
+        if self.jobs.interval_ms == 0 {
+            problems.push("jobs.interval_ms must be greater than 0".to_string());
+        }
+        if self.jobs.role_sync_secs == 0 {
+            problems.push("jobs.role_sync_secs must be greater than 0".to_string());
+        }
+
+        if self.prestige.reset_points >= self.prestige.required_points {
+            problems.push(
+                "prestige.reset_points must be lower than prestige.required_points".to_string(),
+            );
+        }
+        match &self.prestige.reset_rank {
+            None => problems.push("prestige.reset_rank is required".to_string()),
+            Some(rank) if *rank == self.prestige.rank => problems.push(
+                "prestige.reset_rank must be a different rank than prestige.rank".to_string(),
+            ),
+            Some(_) => {}
+        }
+        if self.prestige.badge_group.is_some() != self.prestige.badge_role_id.is_some() {
+            problems.push(
+                "prestige.badge_group and prestige.badge_role_id must be set together".to_string(),
+            );
+        }
+
+        if self.inactivity.action == InactivityAction::Reserve
+            && self.inactivity.reserve_rank.is_none()
+        {
+            problems.push("inactivity.reserve_rank is required for the reserve action".to_string());
+        }
+        if self.inactivity.weeks == 0 || self.inactivity.check_secs == 0 {
+            problems.push(
+                "inactivity.weeks and inactivity.check_secs must be greater than 0".to_string(),
+            );
+        }
+
+        let mut quota_names: Vec<&str> = vec![];
+        for rule in self.quotas.iter() {
+            if quota_names.contains(&rule.name.as_str()) {
+                problems.push(format!("quota {} is defined twice", rule.name));
+            }
+            quota_names.push(&rule.name);
+
+            if rule.min_events == 0 && rule.min_points <= 0 {
+                problems.push(format!(
+                    "quota {} needs min_events or min_points",
+                    rule.name
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("invalid config:\n  {}", problems.join("\n  ")))
+        }
+    }
+
+    /// Files only the server reads, the CLI subcommands run without them
+    pub fn validate_server_files(&self) -> anyhow::Result<()> {
+        let problems = missing_files(vec![
+            ("roblox.cookie_path", Some(&self.roblox.cookie_path)),
+            ("auth.api_keys_path", Some(&self.auth.api_keys_path)),
+            (
+                "auth.signing_secret_path",
+                Some(&self.auth.signing_secret_path),
+            ),
+        ]);
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("invalid config:\n  {}", problems.join("\n  ")))
+        }
+    }
+}
+
+fn missing_files(files: Vec<(&str, Option<&String>)>) -> Vec<String> {
+    let mut problems = vec![];
+    for (name, path) in files {
+        match path {
+            Some(path) if Path::new(path).is_file() => {}
+            Some(path) => problems.push(format!("{} points at missing file {}", name, path)),
+            None => problems.push(format!("{} is required", name)),
+        }
+    }
+
+    problems
+}
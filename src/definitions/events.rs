@@ -0,0 +1,58 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Attendee {
+    pub user_id: u64,
+    /// bP the attendee was awarded for the event
+    #[serde(default)]
+    pub points: i32,
+}
+
+/// An event hosted by an officer, either recorded by the points endpoint or created directly
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Event {
+    #[serde(default)]
+    pub id: String,
+    pub host_id: u64,
+    #[serde(default)]
+    pub place_name: Option<String>,
+    #[serde(rename = "type", default = "default_event_type")]
+    pub event_type: String,
+    pub start_time: SystemTime,
+    #[serde(default)]
+    pub end_time: Option<SystemTime>,
+    #[serde(default)]
+    pub attendees: Vec<Attendee>,
+    /// Points call that recorded the event, if any
+    #[serde(default)]
+    pub batch_id: Option<String>,
+    /// Name of the API key the event was recorded through
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+fn default_event_type() -> String {
+    "event".to_string()
+}
+
+impl Event {
+    pub fn duration(&self) -> Option<Duration> {
+        self.end_time
+            .and_then(|end| end.duration_since(self.start_time).ok())
+    }
+
+    pub fn unix_start(&self) -> i64 {
+        self.start_time
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs() as i64)
+            .unwrap_or_default()
+    }
+
+    pub fn attended(&self, user_id: u64) -> bool {
+        self.attendees
+            .iter()
+            .any(|attendee| attendee.user_id == user_id)
+    }
+}
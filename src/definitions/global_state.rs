@@ -1,68 +1,140 @@
 use crate::{
-    functions::lb::{read_users, write_users},
-    roblox::RobloxAccount,
+    auth::ApiKeyStore, config::Config, roblox::RobloxAccount, signing::RequestSigner,
+    storage::Storage,
 };
-use firebase_realtime_database::Database;
-use log::info;
+
+use super::ranks::RankLadder;
 use parking_lot::RwLock;
-use std::time::Instant;
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+use tokio::sync::Mutex;
 
 use super::users::User;
 
-const LB_REFRESH_TIME: u64 = 60 * 60 * 60;
+/// One user's standing within a week or month
+#[derive(Serialize, Debug, Clone)]
+pub struct PeriodEntry {
+    pub user_id: u64,
+    pub name: String,
+    pub rank: String,
+    pub points: i32,
+    pub events: u32,
+}
+
+/// Totals for the current week or month, rebuilt from the bP logs
+#[derive(Debug, Clone)]
+pub struct PeriodLeaderboard {
+    last_update: Option<Instant>,
+    refresh_secs: u64,
+    /// Start of the period the entries belong to, unix seconds
+    start: i64,
+    entries: Vec<PeriodEntry>,
+}
+
+impl PeriodLeaderboard {
+    pub fn new(refresh_secs: u64) -> Self {
+        PeriodLeaderboard {
+            last_update: None,
+            refresh_secs,
+            start: 0,
+            entries: vec![],
+        }
+    }
+
+    /// Stale once it's older than `refresh_secs` or a new period has started
+    pub fn needs_update(&self, start: i64) -> bool {
+        match self.last_update {
+            Some(last_update) => {
+                self.start != start || last_update.elapsed().as_secs() >= self.refresh_secs
+            }
+            None => true,
+        }
+    }
+
+    pub fn set(&mut self, start: i64, entries: Vec<PeriodEntry>) {
+        self.last_update = Some(Instant::now());
+        self.start = start;
+        self.entries = entries;
+    }
+
+    pub fn get(&self) -> &Vec<PeriodEntry> {
+        &self.entries
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Leaderboard {
     last_update: Instant,
+    refresh_secs: u64,
     sorted: Vec<User>,
+    by_total: Vec<User>,
 }
 
-impl Leaderboard {
-    pub fn new() -> Self {
-        let mut sorted = vec![];
-
-        let result = read_users();
-        if result.is_ok() {
-            sorted = result.unwrap();
-        }
+fn sort_by_total(sorted: &[User]) -> Vec<User> {
+    let mut by_total = sorted.to_vec();
+    by_total.sort_by_key(|user| std::cmp::Reverse(user.total_points));
+    by_total
+}
 
+impl Leaderboard {
+    pub fn new(refresh_secs: u64, sorted: Vec<User>) -> Self {
         Leaderboard {
             last_update: Instant::now(),
+            refresh_secs,
+            by_total: sort_by_total(&sorted),
             sorted,
         }
     }
 
-    pub async fn update(&mut self, db: &Database) {
-        let result = write_users(db).await;
-
-        if result.is_err() {
-            info!("{:?}", result.unwrap_err());
-        }
-
-        match read_users() {
-            Ok(new_sorted) => self.sorted = new_sorted,
-            Err(e) => {
-                info!("{:?}", e);
-            }
-        }
+    /// Replaces the users with a fresh `get_users_by_points`
+    pub fn update(&mut self, sorted: Vec<User>) {
+        self.by_total = sort_by_total(&sorted);
+        self.sorted = sorted;
+        self.last_update = Instant::now();
     }
 
     pub fn needs_update(&self) -> bool {
-        if self.last_update.elapsed().as_secs() >= LB_REFRESH_TIME {
-            true
-        } else {
-            false
-        }
+        self.last_update.elapsed().as_secs() >= self.refresh_secs
     }
 
     pub fn get(&self) -> &Vec<User> {
         &self.sorted
     }
+
+    /// Same users ordered by lifetime bP instead of current bP
+    pub fn get_by_total(&self) -> &Vec<User> {
+        &self.by_total
+    }
 }
 
 // This struct represents state
 pub struct AppState {
-    pub database: RwLock<Database>,
-    pub roblox_user: RwLock<RobloxAccount>,
+    pub config: Config,
+    pub store: Arc<dyn Storage>,
+    pub roblox_user: Mutex<RobloxAccount>,
     pub leaderboard: RwLock<Leaderboard>,
+    pub weekly_leaderboard: RwLock<PeriodLeaderboard>,
+    pub monthly_leaderboard: RwLock<PeriodLeaderboard>,
+    pub api_keys: ApiKeyStore,
+    pub signer: RequestSigner,
+    /// Swapped out whole when the ladder is reloaded, readers keep the `Arc` they got
+    pub ranks: RwLock<Arc<RankLadder>>,
+    /// Cleared while the ladder disagrees with the group's roles, automatic promotion is paused until then
+    pub ladder_in_sync: AtomicBool,
+}
+
+impl AppState {
+    pub fn ladder(&self) -> Arc<RankLadder> {
+        self.ranks.read().clone()
+    }
+
+    pub fn ladder_in_sync(&self) -> bool {
+        self.ladder_in_sync.load(Ordering::Relaxed)
+    }
 }
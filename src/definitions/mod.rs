@@ -1,3 +1,5 @@
+pub mod events;
 pub mod global_state;
 pub mod ranks;
 pub mod users;
+pub mod verification;
@@ -1,207 +1,247 @@
-#![allow(dead_code)]
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+};
 
+use anyhow::{anyhow, Context};
 use serde::{Deserialize, Serialize};
 
+use crate::roblox::GroupRole;
+
+/// One rank of the main group, as defined in the rank ladder file
 #[derive(Serialize, Deserialize, Clone, Debug)]
-pub enum Ranks {
-    Chairman,
-    Marshal,
-    Colonel,
-    Captain,
-    Lieutenant,
-    Ensign,
-    #[serde(rename = "Sergeant Major of the Alliance")]
-    SergeantMajorOfTheAlliance,
-    #[serde(rename = "Staff Sergeant")]
-    StaffSergeant,
-    #[serde(rename = "Tech Sergeant")]
-    TechSergeant,
-    Veteran,
-    Corporal,
-    #[serde(rename = "Lance Corporal")]
-    LanceCorporal,
-    Sentinel,
-    Fleetman,
-    Specialist,
-    Operative,
-    Trooper,
-    Enlisted,
+pub struct RankDefinition {
+    pub name: String,
+    /// Rank number inside the roblox group (0-255)
+    pub value: u64,
+    pub role_id: u64,
+    /// bP needed to hold this rank, `None` for ranks that are only handed out manually
+    #[serde(default)]
+    pub required_points: Option<i32>,
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub prev: Option<String>,
+    /// Whether bP can automatically move users into and out of this rank
+    #[serde(default)]
+    pub promotable: bool,
 }
 
-impl Default for Ranks {
-    fn default() -> Self {
-        Self::Enlisted
-    }
+#[derive(Deserialize)]
+struct RankLadderFile {
+    default: String,
+    ranks: Vec<RankDefinition>,
 }
 
-impl Ranks {
-    pub fn from_value(value: u64) -> Option<Ranks> {
-        match value {
-            255 => Some(Ranks::Chairman),
-            254 => Some(Ranks::Marshal),
-            205 => Some(Ranks::Colonel),
-            198 => Some(Ranks::Captain),
-            196 => Some(Ranks::Lieutenant),
-            193 => Some(Ranks::Ensign),
-            192 => Some(Ranks::SergeantMajorOfTheAlliance),
-            191 => Some(Ranks::StaffSergeant),
-            190 => Some(Ranks::TechSergeant),
-            26 => Some(Ranks::Veteran),
-            25 => Some(Ranks::Corporal),
-            21 => Some(Ranks::LanceCorporal),
-            19 => Some(Ranks::Sentinel),
-            17 => Some(Ranks::Fleetman),
-            15 => Some(Ranks::Specialist),
-            9 => Some(Ranks::Operative),
-            7 => Some(Ranks::Trooper),
-            5 => Some(Ranks::Enlisted),
-            _ => None,
-        }
+/// Outcome of comparing the ladder against the roles that exist in the group
+#[derive(Debug, Default)]
+pub struct LadderSync {
+    /// Ranks whose role was recreated, already pointed at the new role id
+    pub updated: Vec<String>,
+    /// Mismatches someone has to fix in the ladder file or the group
+    pub problems: Vec<String>,
+}
+
+/// The main group's ranks and how they chain together.
+/// Loaded from a toml file so restructures don't need a redeploy
+#[derive(Clone, Debug)]
+pub struct RankLadder {
+    ranks: Vec<RankDefinition>,
+    by_name: HashMap<String, usize>,
+    default_rank: String,
+}
+
+impl RankLadder {
+    pub fn new(ranks: Vec<RankDefinition>, default_rank: String) -> anyhow::Result<Self> {
+        let by_name = ranks
+            .iter()
+            .enumerate()
+            .map(|(i, rank)| (rank.name.clone(), i))
+            .collect();
+
+        let ladder = RankLadder {
+            ranks,
+            by_name,
+            default_rank,
+        };
+        ladder.validate()?;
+
+        Ok(ladder)
     }
 
-    pub fn to_value(&self) -> u64 {
-        match &self {
-            Ranks::Chairman => 255,
-            Ranks::Marshal => 254,
-            Ranks::Colonel => 205,
-            Ranks::Captain => 198,
-            Ranks::Lieutenant => 196,
-            Ranks::Ensign => 193,
-            Ranks::SergeantMajorOfTheAlliance => 192,
-            Ranks::StaffSergeant => 191,
-            Ranks::TechSergeant => 190,
-            Ranks::Veteran => 26,
-            Ranks::Corporal => 25,
-            Ranks::LanceCorporal => 21,
-            Ranks::Sentinel => 19,
-            Ranks::Fleetman => 17,
-            Ranks::Specialist => 15,
-            Ranks::Operative => 9,
-            Ranks::Trooper => 7,
-            Ranks::Enlisted => 5,
-        }
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read rank ladder {}", path))?;
+        let file: RankLadderFile = toml::from_str(&contents)
+            .with_context(|| format!("failed to parse rank ladder {}", path))?;
+
+        RankLadder::new(file.ranks, file.default)
+            .with_context(|| format!("invalid rank ladder {}", path))
     }
 
-    pub fn to_role_id(&self) -> u64 {
-        match &self {
-            Ranks::Chairman => 25617739,
-            Ranks::Marshal => 25617740,
-            Ranks::Colonel => 25617767,
-            Ranks::Captain => 25617779,
-            Ranks::Lieutenant => 25617781,
-            Ranks::Ensign => 25617796,
-            Ranks::SergeantMajorOfTheAlliance => 80131938,
-            Ranks::StaffSergeant => 80131906,
-            Ranks::TechSergeant => 80131913,
-            Ranks::Veteran => 26253933,
-            Ranks::Corporal => 25617802,
-            Ranks::LanceCorporal => 26539946,
-            Ranks::Sentinel => 26539927,
-            Ranks::Fleetman => 26539923,
-            Ranks::Specialist => 25617809,
-            Ranks::Operative => 26539897,
-            Ranks::Trooper => 26539881,
-            Ranks::Enlisted => 25617741,
+    /// Rejects ladders with duplicate or dangling ranks, next/prev links that disagree,
+    /// cycles, and bP thresholds that don't increase along the promotable chain
+    fn validate(&self) -> anyhow::Result<()> {
+        let mut problems: Vec<String> = vec![];
+
+        if self.by_name.len() != self.ranks.len() {
+            problems.push("rank names must be unique".to_string());
         }
-    }
+        let values: HashSet<u64> = self.ranks.iter().map(|r| r.value).collect();
+        if values.len() != self.ranks.len() {
+            problems.push("rank values must be unique".to_string());
+        }
+        if self.get(&self.default_rank).is_none() {
+            problems.push(format!("default rank {} is not defined", self.default_rank));
+        }
+
+        for rank in &self.ranks {
+            if rank.promotable && rank.required_points.is_none() {
+                problems.push(format!(
+                    "{} is promotable but has no required_points",
+                    rank.name
+                ));
+            }
+
+            if let Some(next_name) = &rank.next {
+                match self.get(next_name) {
+                    None => {
+                        problems.push(format!("{} has unknown next rank {}", rank.name, next_name))
+                    }
+                    Some(next) => {
+                        if next.prev.as_ref() != Some(&rank.name) {
+                            problems.push(format!(
+                                "{} lists {} as next, but {} does not list it as prev",
+                                rank.name, next.name, next.name
+                            ));
+                        }
+                        if let (true, true, Some(points), Some(next_points)) = (
+                            rank.promotable,
+                            next.promotable,
+                            rank.required_points,
+                            next.required_points,
+                        ) {
+                            if next_points <= points {
+                                problems.push(format!(
+                                    "{} requires {} bP, which is not more than {} at {}",
+                                    next.name, next_points, points, rank.name
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
 
-    pub fn get_next(&self) -> Option<Ranks> {
-        match &self {
-            Ranks::Chairman => None,
-            Ranks::Marshal => Some(Ranks::Chairman),
-            Ranks::Colonel => Some(Ranks::Marshal),
-            Ranks::Captain => Some(Ranks::Colonel),
-            Ranks::Lieutenant => Some(Ranks::Captain),
-            Ranks::Ensign => Some(Ranks::Lieutenant),
-            Ranks::SergeantMajorOfTheAlliance => Some(Ranks::Ensign),
-            Ranks::StaffSergeant => Some(Ranks::SergeantMajorOfTheAlliance),
-            Ranks::TechSergeant => Some(Ranks::TechSergeant),
-            Ranks::Veteran => None,
-            Ranks::Corporal => Some(Ranks::TechSergeant),
-            Ranks::LanceCorporal => Some(Ranks::Corporal),
-            Ranks::Sentinel => Some(Ranks::LanceCorporal),
-            Ranks::Fleetman => Some(Ranks::Sentinel),
-            Ranks::Specialist => Some(Ranks::Fleetman),
-            Ranks::Operative => Some(Ranks::Specialist),
-            Ranks::Trooper => Some(Ranks::Operative),
-            Ranks::Enlisted => Some(Ranks::Trooper),
+            if let Some(prev_name) = &rank.prev {
+                match self.get(prev_name) {
+                    None => {
+                        problems.push(format!("{} has unknown prev rank {}", rank.name, prev_name))
+                    }
+                    Some(prev) => {
+                        if prev.next.as_ref() != Some(&rank.name) {
+                            problems.push(format!(
+                                "{} lists {} as prev, but {} does not list it as next",
+                                rank.name, prev.name, prev.name
+                            ));
+                        }
+                    }
+                }
+            }
+
+            let mut seen: HashSet<&str> = HashSet::new();
+            let mut current = Some(rank);
+            while let Some(r) = current {
+                if !seen.insert(&r.name) {
+                    problems.push(format!(
+                        "following next from {} loops back to {}",
+                        rank.name, r.name
+                    ));
+                    break;
+                }
+                current = r.next.as_ref().and_then(|n| self.get(n));
+            }
         }
-    }
 
-    pub fn get_prev(&self) -> Option<Ranks> {
-        match &self {
-            Ranks::Chairman => Some(Ranks::Marshal),
-            Ranks::Marshal => Some(Ranks::Colonel),
-            Ranks::Colonel => Some(Ranks::Captain),
-            Ranks::Captain => Some(Ranks::Lieutenant),
-            Ranks::Lieutenant => Some(Ranks::Ensign),
-            Ranks::Ensign => Some(Ranks::SergeantMajorOfTheAlliance),
-            Ranks::SergeantMajorOfTheAlliance => Some(Ranks::StaffSergeant),
-            Ranks::StaffSergeant => Some(Ranks::TechSergeant),
-            Ranks::TechSergeant => Some(Ranks::Corporal),
-            Ranks::Veteran => None,
-            Ranks::Corporal => Some(Ranks::LanceCorporal),
-            Ranks::LanceCorporal => Some(Ranks::Sentinel),
-            Ranks::Sentinel => Some(Ranks::Fleetman),
-            Ranks::Fleetman => Some(Ranks::Specialist),
-            Ranks::Specialist => Some(Ranks::Operative),
-            Ranks::Operative => Some(Ranks::Trooper),
-            Ranks::Trooper => Some(Ranks::Enlisted),
-            Ranks::Enlisted => None,
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("{}", problems.join("\n")))
         }
     }
 
-    pub fn inverse_to_string(str: String) -> Option<Ranks> {
-        match str.as_ref() {
-            "Chairman" => Some(Ranks::Chairman),
-            "Marshal" => Some(Ranks::Marshal),
-            "Colonel" => Some(Ranks::Colonel),
-            "Captain" => Some(Ranks::Captain),
-            "Lieutenant" => Some(Ranks::Lieutenant),
-            "Ensign" => Some(Ranks::Ensign),
-            "Sergeant Major of the Alliance" => Some(Ranks::SergeantMajorOfTheAlliance),
-            "Staff Sergeant" => Some(Ranks::StaffSergeant),
-            "Tech Sergeant" => Some(Ranks::TechSergeant),
-            "Veteran" => Some(Ranks::Veteran),
-            "Corporal" => Some(Ranks::Corporal),
-            "Lance Corporal" => Some(Ranks::LanceCorporal),
-            "Sentinel" => Some(Ranks::Sentinel),
-            "Fleetman" => Some(Ranks::Fleetman),
-            "Specialist" => Some(Ranks::Specialist),
-            "Operative" => Some(Ranks::Operative),
-            "Trooper" => Some(Ranks::Trooper),
-            "Enlisted" => Some(Ranks::Enlisted),
-            _ => None,
+    /// Matches every rank to a group role by name. Changed role ids are fixed in place,
+    /// anything else is reported since we can't tell which side is wrong
+    pub fn sync_roles(&mut self, roles: &[GroupRole]) -> LadderSync {
+        let mut sync = LadderSync::default();
+
+        for rank in self.ranks.iter_mut() {
+            if let Some(role) = roles.iter().find(|role| role.name == rank.name) {
+                if role.rank != rank.value {
+                    sync.problems.push(format!(
+                        "{} is rank {} in the group but {} in the ladder",
+                        rank.name, role.rank, rank.value
+                    ));
+                }
+                if role.id != rank.role_id {
+                    sync.updated.push(format!(
+                        "{} role id {} -> {}",
+                        rank.name, rank.role_id, role.id
+                    ));
+                    rank.role_id = role.id;
+                }
+            } else if let Some(role) = roles.iter().find(|role| role.id == rank.role_id) {
+                sync.problems.push(format!(
+                    "{} was renamed to {} in the group",
+                    rank.name, role.name
+                ));
+            } else if let Some(role) = roles.iter().find(|role| role.rank == rank.value) {
+                sync.problems.push(format!(
+                    "{} is missing from the group, rank {} is now {}",
+                    rank.name, rank.value, role.name
+                ));
+            } else {
+                sync.problems
+                    .push(format!("{} is missing from the group", rank.name));
+            }
         }
+
+        sync
     }
-}
 
-impl ToString for Ranks {
-    fn to_string(&self) -> String {
-        match &self {
-            Ranks::Chairman => String::from("Chairman"),
-            Ranks::Marshal => String::from("Marshal"),
-            Ranks::Colonel => String::from("Colonel"),
-            Ranks::Captain => String::from("Captain"),
-            Ranks::Lieutenant => String::from("Lieutenant"),
-            Ranks::Ensign => String::from("Ensign"),
-            Ranks::SergeantMajorOfTheAlliance => String::from("Sergeant Major of the Alliance"),
-            Ranks::StaffSergeant => String::from("Staff Sergeant"),
-            Ranks::TechSergeant => String::from("Tech Sergeant"),
-            Ranks::Veteran => String::from("Veteran"),
-            Ranks::Corporal => String::from("Corporal"),
-            Ranks::LanceCorporal => String::from("Lance Corporal"),
-            Ranks::Sentinel => String::from("Sentinel"),
-            Ranks::Fleetman => String::from("Fleetman"),
-            Ranks::Specialist => String::from("Specialist"),
-            Ranks::Operative => String::from("Operative"),
-            Ranks::Trooper => String::from("Trooper"),
-            Ranks::Enlisted => String::from("Enlisted"),
-        }
+    pub fn get(&self, name: &str) -> Option<&RankDefinition> {
+        self.by_name.get(name).map(|&i| &self.ranks[i])
+    }
+
+    pub fn by_value(&self, value: u64) -> Option<&RankDefinition> {
+        self.ranks.iter().find(|rank| rank.value == value)
+    }
+
+    pub fn next(&self, name: &str) -> Option<&RankDefinition> {
+        self.get(name)?.next.as_ref().and_then(|n| self.get(n))
+    }
+
+    pub fn prev(&self, name: &str) -> Option<&RankDefinition> {
+        self.get(name)?.prev.as_ref().and_then(|p| self.get(p))
+    }
+
+    /// bP needed to hold `name`, only for ranks that take part in automatic promotion
+    pub fn required_points(&self, name: &str) -> Option<i32> {
+        self.get(name)
+            .filter(|rank| rank.promotable)
+            .and_then(|rank| rank.required_points)
+    }
+
+    /// Rank given to users created without one
+    pub fn default_rank(&self) -> &str {
+        &self.default_rank
+    }
+
+    pub fn ranks(&self) -> &[RankDefinition] {
+        &self.ranks
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub enum STRanks {
     Chairman,
     Marshal,
@@ -213,29 +253,26 @@ pub enum STRanks {
     OpsChief,
     Infiltrator,
     Operative,
+    #[default]
     Trooper,
     Veteran,
 }
 
-impl Default for STRanks {
-    fn default() -> Self {
-        Self::Trooper
-    }
-}
+impl fmt::Display for STRanks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match &self {
+            STRanks::Chairman => "Chairman",
+            STRanks::Marshal => "Marshal",
+            STRanks::ChiefOfStaff => "Chief of Staff",
+            STRanks::ChiefAdvisor => "Chief Advisor",
+            STRanks::OpsChief => "Ops Chief",
+            STRanks::Infiltrator => "Infiltrator",
+            STRanks::Operative => "Operative",
+            STRanks::Trooper => "Trooper",
+            STRanks::Veteran => "Veteran",
+        };
 
-impl ToString for STRanks {
-    fn to_string(&self) -> String {
-        match &self {
-            STRanks::Chairman => String::from("Chairman"),
-            STRanks::Marshal => String::from("Marshal"),
-            STRanks::ChiefOfStaff => String::from("Chief of Staff"),
-            STRanks::ChiefAdvisor => String::from("Chief Advisor"),
-            STRanks::OpsChief => String::from("Ops Chief"),
-            STRanks::Infiltrator => String::from("Infiltrator"),
-            STRanks::Operative => String::from("Operative"),
-            STRanks::Trooper => String::from("Trooper"),
-            STRanks::Veteran => String::from("Veteran"),
-        }
+        write!(f, "{}", name)
     }
 }
 
@@ -256,30 +293,27 @@ impl STRanks {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub enum SableRanks {
     Chairman,
     Marshal,
     Executive,
     Consultant,
+    #[default]
     Contractor,
 }
 
-impl Default for SableRanks {
-    fn default() -> Self {
-        Self::Contractor
-    }
-}
+impl fmt::Display for SableRanks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match &self {
+            SableRanks::Chairman => "Chairman",
+            SableRanks::Marshal => "Marshal",
+            SableRanks::Executive => "Executive",
+            SableRanks::Consultant => "Consultant",
+            SableRanks::Contractor => "Contractor",
+        };
 
-impl ToString for SableRanks {
-    fn to_string(&self) -> String {
-        match &self {
-            SableRanks::Chairman => String::from("Chairman"),
-            SableRanks::Marshal => String::from("Marshal"),
-            SableRanks::Executive => String::from("Executive"),
-            SableRanks::Consultant => String::from("Consultant"),
-            SableRanks::Contractor => String::from("Contractor"),
-        }
+        write!(f, "{}", name)
     }
 }
 
@@ -295,3 +329,116 @@ impl SableRanks {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{rank, ranks};
+
+    fn role(id: u64, name: &str, rank: u64) -> GroupRole {
+        GroupRole {
+            id,
+            name: name.to_string(),
+            rank,
+        }
+    }
+
+    fn problems(ranks: Vec<RankDefinition>) -> String {
+        RankLadder::new(ranks, "Enlisted".to_string())
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn accepts_a_consistent_ladder() {
+        let ladder = RankLadder::new(ranks(), "Enlisted".to_string()).unwrap();
+
+        assert_eq!(ladder.next("Corporal").unwrap().name, "Sergeant");
+        assert_eq!(ladder.prev("Corporal").unwrap().name, "Enlisted");
+        assert_eq!(ladder.by_value(15).unwrap().name, "Sergeant");
+        assert_eq!(ladder.required_points("Officer"), None);
+    }
+
+    #[test]
+    fn rejects_an_unknown_default() {
+        let error = RankLadder::new(ranks(), "Private".to_string())
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("default rank Private"));
+    }
+
+    #[test]
+    fn rejects_links_that_disagree() {
+        let mut ranks = ranks();
+        ranks[2].prev = None;
+
+        assert!(problems(ranks).contains("does not list it as prev"));
+    }
+
+    #[test]
+    fn rejects_thresholds_that_dont_increase() {
+        let mut ranks = ranks();
+        ranks[1].required_points = Some(100);
+
+        assert!(problems(ranks).contains("Sergeant requires 100 bP"));
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let ranks = vec![
+            rank("Enlisted", 5, Some(0), Some("Corporal"), Some("Corporal")),
+            rank(
+                "Corporal",
+                10,
+                Some(100),
+                Some("Enlisted"),
+                Some("Enlisted"),
+            ),
+        ];
+
+        assert!(problems(ranks).contains("loops back"));
+    }
+
+    #[test]
+    fn sync_follows_recreated_roles_and_reports_the_rest() {
+        let mut ladder = RankLadder::new(ranks(), "Enlisted".to_string()).unwrap();
+        let roles = vec![
+            role(2000, "Officer", 20),
+            role(9999, "Sergeant", 15),
+            role(1000, "Corporals", 10),
+            role(500, "Enlisted", 6),
+        ];
+
+        let sync = ladder.sync_roles(&roles);
+
+        assert_eq!(sync.updated, ["Sergeant role id 1500 -> 9999"]);
+        assert_eq!(ladder.get("Sergeant").unwrap().role_id, 9999);
+        assert_eq!(
+            sync.problems,
+            [
+                "Corporal was renamed to Corporals in the group",
+                "Enlisted is rank 6 in the group but 5 in the ladder",
+            ]
+        );
+    }
+
+    #[test]
+    fn sync_reports_missing_ranks() {
+        let mut ladder = RankLadder::new(ranks(), "Enlisted".to_string()).unwrap();
+        let roles = vec![
+            role(2000, "Officer", 20),
+            role(1500, "Sergeant", 15),
+            role(1000, "Corporal", 10),
+            role(42, "Recruit", 5),
+        ];
+
+        let sync = ladder.sync_roles(&roles);
+
+        assert!(sync.updated.is_empty());
+        assert_eq!(
+            sync.problems,
+            ["Enlisted is missing from the group, rank 5 is now Recruit"]
+        );
+    }
+}
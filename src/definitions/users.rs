@@ -1,10 +1,8 @@
-use super::ranks::{Ranks, STRanks, SableRanks};
-use chrono::Utc;
+use super::ranks::{STRanks, SableRanks};
+use crate::config::TotalPointsPolicy;
+use chrono::{NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
-
-pub static WIJ_ID: u64 = 3747606;
-pub static ST_ID: u64 = 3758883;
-pub static SABLE_ID: u64 = 5430057;
+use std::{collections::HashSet, fmt, time::SystemTime};
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Divisions {
@@ -14,56 +12,116 @@ pub struct Divisions {
     pub sable: Option<SableRanks>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Division {
+    St,
+    Sable,
+}
+
+impl Divisions {
+    /// Display name of the member's rank in `division`, if they have one
+    pub fn rank_in(&self, division: Division) -> Option<String> {
+        match division {
+            Division::St => self.st.as_ref().map(ToString::to_string),
+            Division::Sable => self.sable.as_ref().map(ToString::to_string),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct BPLog {
-    time: String,
-    awarder: u64,
-    amount: i32,
-    place_name: Option<String>,
+    /// Stable id used to revert the entry, older entries get one from `User::ensure_log_ids`
+    #[serde(default)]
+    pub id: Option<String>,
+    pub time: String,
+    pub awarder: u64,
+    pub amount: i32,
+    pub place_name: Option<String>,
+    /// Name of the API key the award was made through
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Id of the entry this one compensates for
+    #[serde(default)]
+    pub reverts: Option<String>,
+    /// Shared by every entry written by one call to the points endpoint
+    #[serde(default)]
+    pub batch_id: Option<String>,
+}
+
+/// Why a bP log can't be reverted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevertError {
+    NotFound,
+    IsRevert,
+    AlreadyReverted,
+}
+
+impl fmt::Display for RevertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            RevertError::NotFound => "no such bP log",
+            RevertError::IsRevert => "bP log is itself a revert",
+            RevertError::AlreadyReverted => "bP log was already reverted",
+        };
+
+        write!(f, "{}", reason)
+    }
 }
 
 impl BPLog {
-    pub fn new(awarder: u64, amount: i32) -> Self {
+    pub fn new(awarder: u64, amount: i32, api_key: &str) -> Self {
         BPLog {
+            id: Some(format!("{:016x}", rand::random::<u64>())),
             time: Utc::now().to_string(),
             awarder,
             amount,
             place_name: None,
+            api_key: Some(api_key.to_string()),
+            reverts: None,
+            batch_id: None,
         }
     }
 
+    /// A log cancelling out `original`, awarded by `awarder`
+    pub fn revert(original: &BPLog, awarder: u64, api_key: &str) -> Self {
+        let mut log = BPLog::new(awarder, -original.amount, api_key);
+        log.place_name = original.place_name.clone();
+        log.reverts = original.id.clone();
+        log
+    }
+
+    /// Unix seconds of `time`, which is stored in chrono's display format
+    pub fn unix_time(&self) -> Option<i64> {
+        let time = self.time.trim_end_matches(" UTC");
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S%.f")
+            .ok()
+            .map(|time| time.timestamp())
+    }
+
     pub fn add_place(&mut self, place_name: &str) {
         self.place_name = Some(place_name.to_string());
     }
 }
 
+/// Excuses a member from inactivity checks until `end_time`
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct User {
+pub struct LeaveOfAbsence {
+    pub start_time: SystemTime,
+    pub end_time: SystemTime,
     #[serde(default)]
-    pub user_id: u64,
-    pub name: String,
-    #[serde(default)]
-    pub points: i32,
-    #[serde(default)]
-    pub total_points: i32,
-    #[serde(default)]
-    pub events: u64,
-    #[serde(default)]
-    pub floor_points: Option<i32>,
-    #[serde(default)]
-    pub goal_points: Option<i32>,
-    #[serde(default)]
-    pub rank: Ranks,
-    pub divisions: Option<Divisions>,
-
-    #[serde(default)]
-    pub prestige: Option<i32>,
+    pub reason: Option<String>,
+    pub granted_by: u64,
+}
 
-    pub bp_logs: Option<Vec<BPLog>>,
+impl LeaveOfAbsence {
+    pub fn covers(&self, time: SystemTime) -> bool {
+        time >= self.start_time && time <= self.end_time
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct DeserializeUser {
+pub struct User {
     #[serde(default)]
     pub user_id: u64,
     pub name: String,
@@ -77,6 +135,7 @@ pub struct DeserializeUser {
     pub floor_points: Option<i32>,
     #[serde(default)]
     pub goal_points: Option<i32>,
+    /// Name of a rank in the ladder, see `RankLadder`
     #[serde(default)]
     pub rank: String,
     pub divisions: Option<Divisions>,
@@ -85,4 +144,206 @@ pub struct DeserializeUser {
     pub prestige: Option<i32>,
 
     pub bp_logs: Option<Vec<BPLog>>,
+
+    #[serde(default)]
+    pub loa: Option<LeaveOfAbsence>,
+
+    /// Unix seconds when the inactivity job last flagged or ranked the user,
+    /// they aren't picked up again until they earn a newer award
+    #[serde(default)]
+    pub inactivity_handled: Option<i64>,
+}
+
+impl User {
+    /// Adds an award to the user's points, counting it towards the lifetime total per `policy`
+    pub fn add_points(&mut self, amount: i32, policy: TotalPointsPolicy) {
+        self.points += amount;
+        self.total_points += policy.lifetime_amount(amount);
+    }
+
+    /// Cancels out the award `log_id` with a compensating entry and takes it back out
+    /// of the points and the lifetime total
+    pub fn revert_log(
+        &mut self,
+        log_id: &str,
+        awarder: u64,
+        api_key: &str,
+        policy: TotalPointsPolicy,
+    ) -> Result<BPLog, RevertError> {
+        let original = self.find_log(log_id).ok_or(RevertError::NotFound)?;
+        if original.reverts.is_some() {
+            return Err(RevertError::IsRevert);
+        }
+        if self.is_reverted(log_id) {
+            return Err(RevertError::AlreadyReverted);
+        }
+
+        let revert = BPLog::revert(original, awarder, api_key);
+        self.points += revert.amount;
+        self.total_points -= policy.lifetime_amount(-revert.amount);
+        self.bp_logs
+            .get_or_insert_with(Vec::new)
+            .push(revert.clone());
+
+        Ok(revert)
+    }
+
+    /// Lifetime total as recorded in the bP logs
+    pub fn logged_total(&self, policy: TotalPointsPolicy) -> i32 {
+        self.bp_logs
+            .iter()
+            .flatten()
+            .map(|log| match log.reverts {
+                Some(_) => -policy.lifetime_amount(-log.amount),
+                None => policy.lifetime_amount(log.amount),
+            })
+            .sum()
+    }
+
+    /// Points the user holds that no bP log accounts for, like the rank floor they were
+    /// lifted to when they joined. Never negative, so points lost to a prestige don't count
+    pub fn unlogged_points(&self) -> i32 {
+        let logged: i32 = self.bp_logs.iter().flatten().map(|log| log.amount).sum();
+        (self.points - logged).max(0)
+    }
+
+    /// Gives logs written before ids existed one based on their position, which never changes
+    pub fn ensure_log_ids(&mut self) {
+        for (position, log) in self.bp_logs.iter_mut().flatten().enumerate() {
+            if log.id.is_none() {
+                log.id = Some(format!("legacy-{}", position));
+            }
+        }
+    }
+
+    pub fn find_log(&self, log_id: &str) -> Option<&BPLog> {
+        self.bp_logs
+            .iter()
+            .flatten()
+            .find(|log| log.id.as_deref() == Some(log_id))
+    }
+
+    pub fn is_reverted(&self, log_id: &str) -> bool {
+        self.bp_logs
+            .iter()
+            .flatten()
+            .any(|log| log.reverts.as_deref() == Some(log_id))
+    }
+
+    /// Unix seconds of the most recent award that still stands
+    pub fn last_active(&self) -> Option<i64> {
+        self.standing_logs()
+            .iter()
+            .filter(|log| log.amount > 0)
+            .filter_map(|log| log.unix_time())
+            .max()
+    }
+
+    /// Awards that still stand, i.e. everything except reverts and the entries they cancel.
+    /// Expects `ensure_log_ids` to have run so legacy entries can be matched
+    pub fn standing_logs(&self) -> Vec<&BPLog> {
+        let reverted: HashSet<&str> = self
+            .bp_logs
+            .iter()
+            .flatten()
+            .filter_map(|log| log.reverts.as_deref())
+            .collect();
+
+        self.bp_logs
+            .iter()
+            .flatten()
+            .filter(|log| log.reverts.is_none())
+            .filter(|log| !log.id.as_deref().is_some_and(|id| reverted.contains(id)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::user;
+
+    /// Awards `amount` the way the points endpoint does, returning the new log's id
+    fn award(user: &mut User, amount: i32, policy: TotalPointsPolicy) -> String {
+        let log = BPLog::new(7, amount, "test");
+        let id = log.id.clone().unwrap();
+        user.add_points(amount, policy);
+        user.bp_logs.get_or_insert_with(Vec::new).push(log);
+        id
+    }
+
+    #[test]
+    fn revert_takes_the_award_back_out() {
+        let policy = TotalPointsPolicy::PositiveOnly;
+        let mut user = user("Enlisted", 0);
+        let id = award(&mut user, 10, policy);
+
+        let revert = user.revert_log(&id, 8, "test", policy).unwrap();
+
+        assert_eq!(revert.amount, -10);
+        assert_eq!(revert.reverts.as_deref(), Some(id.as_str()));
+        assert_eq!(user.points, 0);
+        assert_eq!(user.total_points, 0);
+        assert!(user.is_reverted(&id));
+        assert!(user.standing_logs().is_empty());
+    }
+
+    #[test]
+    fn revert_is_refused_twice_and_for_reverts() {
+        let policy = TotalPointsPolicy::PositiveOnly;
+        let mut user = user("Enlisted", 0);
+        let id = award(&mut user, 10, policy);
+        let revert = user.revert_log(&id, 8, "test", policy).unwrap();
+
+        assert_eq!(
+            user.revert_log(&id, 8, "test", policy).unwrap_err(),
+            RevertError::AlreadyReverted
+        );
+        assert_eq!(
+            user.revert_log(revert.id.as_deref().unwrap(), 8, "test", policy)
+                .unwrap_err(),
+            RevertError::IsRevert
+        );
+        assert_eq!(
+            user.revert_log("missing", 8, "test", policy).unwrap_err(),
+            RevertError::NotFound
+        );
+        assert_eq!(user.points, 0);
+    }
+
+    #[test]
+    fn reverted_deduction_leaves_positive_only_total_alone() {
+        let policy = TotalPointsPolicy::PositiveOnly;
+        let mut user = user("Enlisted", 0);
+        award(&mut user, 10, policy);
+        let id = award(&mut user, -4, policy);
+        user.revert_log(&id, 8, "test", policy).unwrap();
+
+        assert_eq!(user.points, 10);
+        assert_eq!(user.total_points, 10);
+        assert_eq!(user.logged_total(policy), user.total_points);
+    }
+
+    #[test]
+    fn logged_total_follows_the_policy() {
+        let mut user = user("Enlisted", 0);
+        award(&mut user, 10, TotalPointsPolicy::Net);
+        award(&mut user, -4, TotalPointsPolicy::Net);
+        let id = award(&mut user, 6, TotalPointsPolicy::Net);
+        user.revert_log(&id, 8, "test", TotalPointsPolicy::Net)
+            .unwrap();
+
+        assert_eq!(user.logged_total(TotalPointsPolicy::Net), 6);
+        assert_eq!(user.logged_total(TotalPointsPolicy::PositiveOnly), 10);
+        assert_eq!(user.total_points, 6);
+    }
+
+    #[test]
+    fn unlogged_points_are_what_the_logs_dont_explain() {
+        let policy = TotalPointsPolicy::PositiveOnly;
+        let mut user = user("Corporal", 100);
+        award(&mut user, 10, policy);
+
+        assert_eq!(user.unlogged_points(), 100);
+    }
 }
@@ -0,0 +1,111 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VerificationState {
+    #[default]
+    Pending,
+    Confirmed,
+    Expired,
+    Revoked,
+}
+
+impl fmt::Display for VerificationState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match &self {
+            VerificationState::Pending => "pending",
+            VerificationState::Confirmed => "confirmed",
+            VerificationState::Expired => "expired",
+            VerificationState::Revoked => "revoked",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl VerificationState {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pending" => Some(VerificationState::Pending),
+            "confirmed" => Some(VerificationState::Confirmed),
+            "expired" => Some(VerificationState::Expired),
+            "revoked" => Some(VerificationState::Revoked),
+            _ => None,
+        }
+    }
+}
+
+/// A verification request, stored under the one-time code shown to the discord user
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VerificationBody {
+    pub discord_id: String,
+    pub creation_time: SystemTime,
+    /// Roblox account the code was issued for, unset on requests made before codes
+    #[serde(default)]
+    pub roblox_id: Option<u64>,
+    #[serde(default)]
+    pub state: VerificationState,
+}
+
+impl VerificationBody {
+    pub fn expires_at(&self, timeout_ms: u64) -> SystemTime {
+        self.creation_time + Duration::from_millis(timeout_ms)
+    }
+
+    pub fn is_expired(&self, timeout_ms: u64, now: SystemTime) -> bool {
+        now >= self.expires_at(timeout_ms)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct VerifiedStruct {
+    pub roblox_id: u64,
+    pub discord_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkAction {
+    Linked,
+    Unlinked,
+}
+
+impl fmt::Display for LinkAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match &self {
+            LinkAction::Linked => "linked",
+            LinkAction::Unlinked => "unlinked",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl LinkAction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linked" => Some(LinkAction::Linked),
+            "unlinked" => Some(LinkAction::Unlinked),
+            _ => None,
+        }
+    }
+}
+
+/// One change to a discord ↔ roblox link, kept for moderation. A relink is an
+/// `Unlinked` record for the old account followed by a `Linked` one for the new
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LinkRecord {
+    pub discord_id: String,
+    pub roblox_id: u64,
+    pub action: LinkAction,
+    pub time: SystemTime,
+    /// Name of the api key that made the change
+    pub api_key: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
@@ -0,0 +1,161 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::{
+    config::{InactivityAction, InactivityConfig},
+    definitions::{ranks::RankLadder, users::User},
+    logs::log_error,
+    roblox::RobloxAccount,
+    AppState,
+};
+
+use super::users::reconcile_user;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InactiveMember {
+    pub user_id: u64,
+    pub name: String,
+    pub rank: String,
+    /// Unix seconds of their last standing award
+    pub last_active: i64,
+    /// Rank they would be moved to, `None` when they are only flagged
+    pub target_rank: Option<String>,
+}
+
+/// Members whose last award is older than the configured number of weeks.
+/// Users without any awards, on leave, in an exempt rank, or already handled
+/// since their last award are skipped
+pub fn find_inactive(
+    users: &[User],
+    ladder: &RankLadder,
+    config: &InactivityConfig,
+    now: SystemTime,
+) -> Vec<InactiveMember> {
+    let cutoff = (now - Duration::from_secs(config.weeks * 7 * 24 * 60 * 60))
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() as i64)
+        .unwrap_or_default();
+
+    let mut inactive: Vec<InactiveMember> = users
+        .iter()
+        .filter(|user| !config.exempt_ranks.contains(&user.rank))
+        .filter(|user| !user.loa.as_ref().is_some_and(|loa| loa.covers(now)))
+        .filter(|user| config.reserve_rank.as_ref() != Some(&user.rank))
+        .filter_map(|user| {
+            let last_active = user.last_active()?;
+            if last_active >= cutoff
+                || user
+                    .inactivity_handled
+                    .is_some_and(|handled| handled >= last_active)
+            {
+                return None;
+            }
+
+            let target_rank = match config.action {
+                InactivityAction::Flag => None,
+                InactivityAction::Demote => ladder.prev(&user.rank).map(|rank| rank.name.clone()),
+                InactivityAction::Reserve => config.reserve_rank.clone(),
+            };
+
+            Some(InactiveMember {
+                user_id: user.user_id,
+                name: user.name.clone(),
+                rank: user.rank.clone(),
+                last_active,
+                target_rank,
+            })
+        })
+        .collect();
+
+    inactive.sort_by_key(|member| member.last_active);
+    inactive
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Records that the members were flagged, so the next report doesn't repeat them
+pub async fn mark_flagged(members: &[InactiveMember], app_state: &AppState, caller: &str) {
+    let now = unix_now();
+    for member in members {
+        let mut user = match app_state.store.get_user(member.user_id).await {
+            Ok(Some(user)) => user,
+            _ => continue,
+        };
+        user.inactivity_handled = Some(now);
+        if let Err(e) = app_state.store.put_user(&user).await {
+            log_error(
+                &app_state.config.webhooks,
+                format!(
+                    "[{}] Failed to save inactive user {} - {}: {}",
+                    caller, user.user_id, user.name, e
+                ),
+            )
+            .await;
+        }
+    }
+}
+
+/// Moves every member with a target rank there, returns how many were ranked
+pub async fn apply_inactivity(
+    members: &[InactiveMember],
+    app_state: &AppState,
+    roblox_account: &mut RobloxAccount,
+    caller: &str,
+) -> usize {
+    let ladder = app_state.ladder();
+    let mut ranked = 0;
+
+    for member in members {
+        let target = match member
+            .target_rank
+            .as_ref()
+            .and_then(|name| ladder.get(name))
+        {
+            Some(target) => target,
+            None => continue,
+        };
+        let mut user = match app_state.store.get_user(member.user_id).await {
+            Ok(Some(user)) => user,
+            _ => continue,
+        };
+
+        match roblox_account
+            .set_rank(user.user_id, app_state.config.groups.wij, target.role_id)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                log_error(&app_state.config.webhooks, format!("ERROR: {}", e)).await;
+                continue;
+            }
+        }
+
+        // otherwise the next award walks them straight back up the ladder
+        if let Some(required) = ladder.required_points(&target.name) {
+            user.points = user.points.min(required);
+        }
+        user.rank = target.name.clone();
+        user.inactivity_handled = Some(unix_now());
+        if let Err(e) = app_state.store.put_user(&user).await {
+            log_error(
+                &app_state.config.webhooks,
+                format!(
+                    "[{}] Failed to save inactive user {} - {}: {}",
+                    caller, user.user_id, user.name, e
+                ),
+            )
+            .await;
+        }
+        reconcile_user(&mut user, app_state).await;
+        ranked += 1;
+    }
+
+    ranked
+}
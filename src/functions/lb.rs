@@ -1,60 +1,106 @@
-use std::{
-    collections::HashMap,
-    fs::File,
-    io::{BufRead, BufReader, Write},
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::definitions::{
+    global_state::PeriodEntry,
+    users::{Division, User},
 };
 
-use firebase_realtime_database::{Database, FirebaseError};
-use log::info;
-use serde_json::{from_str, to_string};
-use std::io;
-
-use crate::definitions::users::User;
-
-pub async fn write_users(db: &Database) -> io::Result<()> {
-    let response_result = db.get("users").await;
-
-    if response_result.is_err() {
-        let err = response_result.unwrap_err();
-        match err {
-            FirebaseError::GcpAuthError(e) => info!("{:?}", e),
-            FirebaseError::ReqwestError(e) => info!("{:?}", e),
-        }
-        return Ok(());
-    }
-
-    let map_result = response_result
-        .unwrap()
-        .json::<HashMap<String, User>>()
-        .await;
-    if map_result.is_err() {
-        info!("lb jsonify failed with error {:?}", map_result.unwrap_err());
-        return Ok(());
-    }
-
-    let mut vec: Vec<User> = map_result.unwrap().values().map(|v| v.to_owned()).collect();
-    vec.sort_by(|a, b| b.points.cmp(&a.points));
-
-    let mut file = File::create("users")?;
-    file.write(to_string(&vec)?.as_bytes())?;
-
-    Ok(())
+/// Standing awards between `start` and `end` per user, highest first.
+/// Users with no awards in the window are left out
+pub fn period_totals(users: &[User], start: i64, end: i64) -> Vec<PeriodEntry> {
+    let mut entries: Vec<PeriodEntry> = users
+        .iter()
+        .filter_map(|user| {
+            let logs: Vec<_> = user
+                .standing_logs()
+                .into_iter()
+                .filter(|log| {
+                    log.unix_time()
+                        .is_some_and(|time| time >= start && time < end)
+                })
+                .collect();
+            if logs.is_empty() {
+                return None;
+            }
+
+            Some(PeriodEntry {
+                user_id: user.user_id,
+                name: user.name.clone(),
+                rank: user.rank.clone(),
+                points: logs.iter().map(|log| log.amount).sum(),
+                events: logs.iter().filter(|log| log.amount > 0).count() as u32,
+            })
+        })
+        .collect();
+
+    entries.sort_by_key(|entry| std::cmp::Reverse((entry.points, entry.events)));
+    entries
 }
 
-pub fn read_users() -> io::Result<Vec<User>> {
-    let users_file = File::open("users")?;
-    let reader = BufReader::new(users_file);
-    let users_string: String = reader
-        .lines()
-        .map(|s| {
-            if s.is_ok() {
-                s.unwrap()
-            } else {
-                "".to_string()
-            }
+/// One member's standing within a division, next to their main rank
+#[derive(Serialize, Debug, Clone)]
+pub struct DivisionEntry {
+    pub user_id: u64,
+    pub name: String,
+    pub rank: String,
+    pub division_rank: String,
+    pub points: i32,
+    pub total_points: i32,
+}
+
+/// Keeps the members of `division` from an already sorted leaderboard, in the same order
+pub fn division_entries(sorted: &[User], division: Division) -> Vec<DivisionEntry> {
+    sorted
+        .iter()
+        .filter_map(|user| {
+            let division_rank = user.divisions.as_ref()?.rank_in(division)?;
+            Some(DivisionEntry {
+                user_id: user.user_id,
+                name: user.name.clone(),
+                rank: user.rank.clone(),
+                division_rank,
+                points: user.points,
+                total_points: user.total_points,
+            })
         })
+        .collect()
+}
+
+/// One member's standing within a division over a week or month
+#[derive(Serialize, Debug, Clone)]
+pub struct DivisionPeriodEntry {
+    pub user_id: u64,
+    pub name: String,
+    pub rank: String,
+    pub division_rank: String,
+    pub points: i32,
+    pub events: u32,
+}
+
+/// Keeps the entries of a period leaderboard whose user is in `division` among `users`, in the same order
+pub fn division_period_entries(
+    entries: &[PeriodEntry],
+    users: &[User],
+    division: Division,
+) -> Vec<DivisionPeriodEntry> {
+    let division_ranks: HashMap<u64, String> = users
+        .iter()
+        .filter_map(|user| Some((user.user_id, user.divisions.as_ref()?.rank_in(division)?)))
         .collect();
 
-    let users_vec: Vec<User> = from_str(users_string.as_str())?;
-    Ok(users_vec)
+    entries
+        .iter()
+        .filter_map(|entry| {
+            Some(DivisionPeriodEntry {
+                user_id: entry.user_id,
+                name: entry.name.clone(),
+                rank: entry.rank.clone(),
+                division_rank: division_ranks.get(&entry.user_id)?.clone(),
+                points: entry.points,
+                events: entry.events,
+            })
+        })
+        .collect()
 }
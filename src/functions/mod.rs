@@ -1,5 +1,8 @@
+pub mod inactivity;
 pub mod lb;
 pub mod prestige;
 pub mod promotion;
+pub mod quotas;
+pub mod stats;
 pub mod users;
 pub mod verify;
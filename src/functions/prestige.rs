@@ -1,3 +1,177 @@
-use crate::definitions::users::User;
+use serde::Serialize;
 
-pub fn _should_prestige(_user: &User) {}
+use crate::{
+    config::{PrestigeConfig, WebhookConfig},
+    definitions::{
+        ranks::{RankDefinition, RankLadder},
+        users::User,
+    },
+    logs::{log_error, log_to_discord},
+    roblox::RobloxAccount,
+};
+
+use super::promotion::{planned_path, planned_rank};
+
+/// What a prestige does to a user, returned by the prestige endpoint and its preview
+#[derive(Serialize, Debug)]
+pub struct PrestigePlan {
+    pub user_id: u64,
+    pub prestige: i32,
+    pub points_from: i32,
+    pub points_to: i32,
+    pub rank_from: String,
+    pub rank_to: String,
+}
+
+/// Users prestige once their points would put them at the top of the enlisted ladder.
+/// Points at the prestige rank aren't lifted to its floor (see `refresh_user`),
+/// so after a reset this only fires again once the threshold is earned again
+pub fn should_prestige(user: &User, ladder: &RankLadder, config: &PrestigeConfig) -> bool {
+    let rank = planned_rank(user, ladder, config)
+        .map(|rank| rank.name.as_str())
+        .unwrap_or(&user.rank);
+
+    rank == config.rank && user.points >= config.required_points
+}
+
+/// Bumps the prestige level and drops the user back to `reset_points`, `total_points` is left alone.
+/// The rank is changed separately, see `reset_rank`
+pub fn apply_prestige(user: &mut User, config: &PrestigeConfig) {
+    user.prestige = Some(user.prestige.unwrap_or(0) + 1);
+    user.points = config.reset_points;
+}
+
+/// Rank a prestiged user is moved to, if `reset_rank` is in the ladder
+pub fn reset_rank<'a>(
+    ladder: &'a RankLadder,
+    config: &PrestigeConfig,
+) -> Option<&'a RankDefinition> {
+    config
+        .reset_rank
+        .as_deref()
+        .and_then(|rank| ladder.get(rank))
+}
+
+/// Ranks a prestiged user moves through: the reset rank,
+/// then any promotions their remaining points still earn
+pub fn prestige_path<'a>(
+    user: &User,
+    ladder: &'a RankLadder,
+    config: &PrestigeConfig,
+) -> Vec<&'a RankDefinition> {
+    match reset_rank(ladder, config) {
+        Some(rank) if rank.name != user.rank => {
+            let mut moved = user.clone();
+            moved.rank = rank.name.clone();
+            std::iter::once(rank)
+                .chain(planned_path(&moved, ladder, config))
+                .collect()
+        }
+        _ => planned_path(user, ladder, config),
+    }
+}
+
+pub fn plan_prestige(
+    user: &User,
+    ladder: &RankLadder,
+    config: &PrestigeConfig,
+) -> Option<PrestigePlan> {
+    if !should_prestige(user, ladder, config) {
+        return None;
+    }
+
+    let mut after = user.clone();
+    apply_prestige(&mut after, config);
+    let rank_to = prestige_path(&after, ladder, config)
+        .pop()
+        .map(|rank| rank.name.clone())
+        .unwrap_or_else(|| after.rank.clone());
+
+    Some(PrestigePlan {
+        user_id: user.user_id,
+        prestige: after.prestige.unwrap_or_default(),
+        points_from: user.points,
+        points_to: after.points,
+        rank_from: user.rank.clone(),
+        rank_to,
+    })
+}
+
+/// Hands out the optional badge role and announces the prestige
+pub async fn finish_prestige(
+    user: &User,
+    roblox_account: &mut RobloxAccount,
+    config: &PrestigeConfig,
+    webhooks: &WebhookConfig,
+    caller: &str,
+) {
+    let prestige = user.prestige.unwrap_or_default();
+
+    if let (Some(group_id), Some(role_id)) = (config.badge_group, config.badge_role_id) {
+        match roblox_account
+            .set_rank(user.user_id, group_id, role_id)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                log_error(
+                    webhooks,
+                    format!(
+                        "[{}] Failed to give the prestige role to {} - {}",
+                        caller, user.user_id, user.name
+                    ),
+                )
+                .await
+            }
+            Err(e) => log_error(webhooks, format!("ERROR: {}", e)).await,
+        }
+    }
+
+    log_to_discord(
+        webhooks,
+        format!(
+            "[{}] {} - {} reached **prestige {}**!",
+            caller, user.user_id, user.name, prestige
+        ),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{ladder, prestige, user};
+
+    #[test]
+    fn prestiges_once_the_threshold_is_reached_at_the_prestige_rank() {
+        let ladder = ladder();
+        let config = prestige();
+
+        assert!(should_prestige(&user("Sergeant", 300), &ladder, &config));
+        // promoted into the prestige rank by the same award
+        assert!(should_prestige(&user("Corporal", 300), &ladder, &config));
+        assert!(!should_prestige(&user("Sergeant", 299), &ladder, &config));
+        assert!(!should_prestige(&user("Officer", 300), &ladder, &config));
+    }
+
+    #[test]
+    fn plan_resets_points_and_rank() {
+        let plan = plan_prestige(&user("Sergeant", 320), &ladder(), &prestige()).unwrap();
+
+        assert_eq!(plan.prestige, 1);
+        assert_eq!(plan.points_from, 320);
+        assert_eq!(plan.points_to, 0);
+        assert_eq!(plan.rank_to, "Enlisted");
+    }
+
+    #[test]
+    fn reset_user_does_not_prestige_again() {
+        let ladder = ladder();
+        let config = prestige();
+        let mut user = user("Sergeant", 300);
+        apply_prestige(&mut user, &config);
+        user.rank = "Enlisted".to_string();
+
+        assert!(!should_prestige(&user, &ladder, &config));
+    }
+}
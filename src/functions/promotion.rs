@@ -1,150 +1,255 @@
-use firebase_realtime_database::Database;
-
 use crate::{
-    definitions::ranks::Ranks,
+    config::PrestigeConfig,
+    definitions::ranks::{RankDefinition, RankLadder},
     definitions::users::User,
     logs::{log_error, log_to_discord},
     roblox::RobloxAccount,
+    AppState,
 };
 
-use super::users::reconcile_user;
-
-static WIJ_ID: u64 = 3747606;
+use log::info;
 
-pub fn get_required_points(rank: Ranks) -> Option<i32> {
-    match rank {
-        Ranks::StaffSergeant => Some(900),
-        Ranks::TechSergeant => Some(600),
-        Ranks::Corporal => Some(325),
-        Ranks::LanceCorporal => Some(270),
-        Ranks::Sentinel => Some(190),
-        Ranks::Fleetman => Some(115),
-        Ranks::Specialist => Some(65),
-        Ranks::Operative => Some(30),
-        Ranks::Trooper => Some(10),
-        Ranks::Enlisted => Some(0),
-        _ => None,
-    }
-}
+use super::{
+    prestige::{apply_prestige, finish_prestige, prestige_path, should_prestige},
+    users::reconcile_user,
+};
 
-pub fn _get_rank_for_points(points: i32) -> Option<Ranks> {
-    match points {
-        601..=900 => Some(Ranks::StaffSergeant),
-        326..=600 => Some(Ranks::TechSergeant),
-        271..=325 => Some(Ranks::Corporal),
-        191..=270 => Some(Ranks::LanceCorporal),
-        116..=190 => Some(Ranks::Sentinel),
-        66..=115 => Some(Ranks::Fleetman),
-        31..=65 => Some(Ranks::Specialist),
-        11..=30 => Some(Ranks::Operative),
-        1..=10 => Some(Ranks::Trooper),
-        0 => Some(Ranks::Enlisted),
-        _ => None,
+fn can_promote(rank: &str, points: i32, ladder: &RankLadder) -> bool {
+    if ladder.required_points(rank).is_none() {
+        return false;
     }
-}
 
-pub fn should_promote(user: &User) -> bool {
-    let next_rank = if let Some(rank) = user.rank.get_next() {
+    let next_rank = if let Some(rank) = ladder.next(rank) {
         rank
     } else {
         return false;
     };
 
-    let promotion_points = get_required_points(next_rank);
+    let promotion_points = ladder.required_points(&next_rank.name);
     match promotion_points {
-        Some(points) => return user.points >= points,
-        None => return false,
+        Some(required) => points >= required,
+        None => false,
     }
 }
 
-pub fn should_demote(user: &User) -> bool {
-    let prev_rank = if let Some(rank) = user.rank.get_prev() {
+fn can_demote(rank: &str, points: i32, ladder: &RankLadder) -> bool {
+    if ladder.required_points(rank).is_none() {
+        return false;
+    }
+
+    let prev_rank = if let Some(rank) = ladder.prev(rank) {
         rank
     } else {
         return false;
     };
 
-    let demotion_points = get_required_points(prev_rank);
+    let demotion_points = ladder.required_points(&prev_rank.name);
     match demotion_points {
-        Some(points) => return user.points < points,
-        None => return false,
+        Some(required) => points < required,
+        None => false,
     }
 }
 
-pub async fn promote(user: &mut User, roblox_account: &mut RobloxAccount) -> bool {
-    if !should_promote(user) {
-        return false;
+pub fn should_promote(user: &User, ladder: &RankLadder) -> bool {
+    can_promote(&user.rank, user.points, ladder)
+}
+
+/// Every rank the user steps through until their points fit, in order.
+/// Empty when they already hold the right rank. The prestige rank is never demoted from,
+/// its points are reset by the prestige itself
+pub fn planned_path<'a>(
+    user: &User,
+    ladder: &'a RankLadder,
+    prestige: &PrestigeConfig,
+) -> Vec<&'a RankDefinition> {
+    let mut path: Vec<&RankDefinition> = vec![];
+    let mut current = user.rank.as_str();
+
+    // the ladder is validated to be acyclic, the bound only guards against a bad reload
+    while path.len() < ladder.ranks().len() {
+        let step = if can_promote(current, user.points, ladder) {
+            ladder.next(current)
+        } else if current != prestige.rank && can_demote(current, user.points, ladder) {
+            ladder.prev(current)
+        } else {
+            None
+        };
+
+        match step {
+            Some(rank) => {
+                current = &rank.name;
+                path.push(rank);
+            }
+            None => break,
+        }
     }
 
-    let next_rank = if let Some(rank) = user.rank.get_next() {
-        rank
+    path
+}
+
+/// The rank `check_promotion` would move the user to, worked out without touching roblox
+pub fn planned_rank<'a>(
+    user: &User,
+    ladder: &'a RankLadder,
+    prestige: &PrestigeConfig,
+) -> Option<&'a RankDefinition> {
+    planned_path(user, ladder, prestige).pop()
+}
+
+/// Moves the user straight to the end of `path` with a single `set_rank` call
+async fn move_along(
+    user: &mut User,
+    path: &[&RankDefinition],
+    roblox_account: &mut RobloxAccount,
+    app_state: &AppState,
+    caller: &str,
+) -> bool {
+    let final_rank = match path.last() {
+        Some(rank) => rank,
+        None => return false,
+    };
+    let action = if should_promote(user, &app_state.ladder()) {
+        "Promoted"
     } else {
-        return false;
+        "Demoted"
     };
 
-    user.rank = next_rank;
+    let steps: Vec<&str> = std::iter::once(user.rank.as_str())
+        .chain(path.iter().map(|rank| rank.name.as_str()))
+        .collect();
+    let steps = steps.join(" → ");
 
     let result = roblox_account
-        .set_rank(user.user_id, WIJ_ID, user.rank.clone())
+        .set_rank(
+            user.user_id,
+            app_state.config.groups.wij,
+            final_rank.role_id,
+        )
         .await;
 
     match result {
         Ok(b) => {
-            log_to_discord(format!("Promoted user {} - {}", user.user_id, user.name)).await;
-            log_error(format!(
-                "**Promoted** user {} - {}",
-                user.user_id, user.name
-            ))
+            user.rank = final_rank.name.clone();
+            log_to_discord(
+                &app_state.config.webhooks,
+                format!(
+                    "[{}] {} user {} - {}: {}",
+                    caller, action, user.user_id, user.name, steps
+                ),
+            )
+            .await;
+            log_error(
+                &app_state.config.webhooks,
+                format!(
+                    "[{}] **{}** user {} - {}: {}",
+                    caller, action, user.user_id, user.name, steps
+                ),
+            )
             .await;
-            return b;
+            b
         }
         Err(e) => {
-            log_error(format!("ERROR: {}", e.to_string())).await;
-            return false;
+            log_error(&app_state.config.webhooks, format!("ERROR: {}", e)).await;
+            false
         }
     }
 }
 
-pub async fn demote(user: &mut User, roblox_account: &mut RobloxAccount) -> bool {
-    if !should_demote(user) {
-        return false;
+pub async fn check_promotion(
+    user: &mut User,
+    app_state: &AppState,
+    roblox_account: &mut RobloxAccount,
+    caller: &str,
+) {
+    if !app_state.ladder_in_sync() {
+        info!(
+            "rank ladder is out of sync with the group, skipping promotion check for {}",
+            user.user_id
+        );
+        return;
     }
 
-    let prev_rank = if let Some(rank) = user.rank.get_prev() {
-        rank
+    let ladder = app_state.ladder();
+    let prestige = &app_state.config.prestige;
+
+    // prestiging replaces the promotion, so the user only gets ranked once
+    let prestiged = should_prestige(user, &ladder, prestige);
+    if prestiged {
+        apply_prestige(user, prestige);
+    }
+
+    let path = if prestiged {
+        prestige_path(user, &ladder, prestige)
     } else {
-        return false;
+        planned_path(user, &ladder, prestige)
     };
+    if !path.is_empty() {
+        move_along(user, &path, roblox_account, app_state, caller).await;
+    }
 
-    user.rank = prev_rank;
-
-    let result = roblox_account
-        .set_rank(user.user_id, WIJ_ID, user.rank.clone())
+    if prestiged {
+        if let Err(e) = app_state.store.put_user(user).await {
+            log_error(
+                &app_state.config.webhooks,
+                format!(
+                    "[{}] Failed to save prestige for {} - {}: {}",
+                    caller, user.user_id, user.name, e
+                ),
+            )
+            .await;
+        }
+        finish_prestige(
+            user,
+            roblox_account,
+            prestige,
+            &app_state.config.webhooks,
+            caller,
+        )
         .await;
+    }
 
-    match result {
-        Ok(b) => {
-            log_to_discord(format!("Demoted user {}-{}", user.user_id, user.name)).await;
-            log_error(format!("**Demoted** user {} - {}", user.user_id, user.name)).await;
-            return b;
-        }
-        Err(e) => {
-            log_error(format!("ERROR: {}", e.to_string())).await;
-            return false;
-        }
+    if prestiged || !path.is_empty() {
+        reconcile_user(user, app_state).await;
     }
 }
 
-pub async fn check_promotion(
-    user: &mut User,
-    database: &Database,
-    roblox_account: &mut RobloxAccount,
-) {
-    if should_promote(user) {
-        promote(user, roblox_account).await;
-        reconcile_user(user, database).await;
-    } else if should_demote(user) {
-        demote(user, roblox_account).await;
-        reconcile_user(user, database).await;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{ladder, prestige, user};
+
+    fn names(path: Vec<&RankDefinition>) -> Vec<&str> {
+        path.into_iter().map(|rank| rank.name.as_str()).collect()
+    }
+
+    #[test]
+    fn promotes_through_every_rank_the_points_reach() {
+        let ladder = ladder();
+        let path = planned_path(&user("Enlisted", 350), &ladder, &prestige());
+
+        assert_eq!(names(path), ["Corporal", "Sergeant"]);
+    }
+
+    #[test]
+    fn stays_put_when_the_points_fit() {
+        let ladder = ladder();
+
+        assert!(planned_path(&user("Corporal", 150), &ladder, &prestige()).is_empty());
+        assert!(planned_path(&user("Officer", 5000), &ladder, &prestige()).is_empty());
+    }
+
+    #[test]
+    fn demotes_below_the_previous_floor() {
+        let ladder = ladder();
+        let path = planned_path(&user("Corporal", -10), &ladder, &prestige());
+
+        assert_eq!(names(path), ["Enlisted"]);
+    }
+
+    #[test]
+    fn never_demotes_from_the_prestige_rank() {
+        let ladder = ladder();
+
+        assert!(planned_path(&user("Sergeant", 0), &ladder, &prestige()).is_empty());
     }
 }
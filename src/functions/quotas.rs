@@ -0,0 +1,161 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use serde::Serialize;
+
+use crate::{
+    config::{QuotaPeriod, QuotaRule},
+    definitions::{events::Event, users::User},
+};
+
+/// Start and end (exclusive) of the period containing `at`, all in unix seconds.
+/// None when `at` is outside the range chrono can represent
+pub fn period_bounds(period: QuotaPeriod, at: i64) -> Option<(i64, i64)> {
+    let date = NaiveDateTime::from_timestamp_opt(at, 0)?.date();
+    let (start, end) = match period {
+        QuotaPeriod::Weekly => {
+            let start = date
+                .checked_sub_signed(Duration::days(date.weekday().num_days_from_monday() as i64))?;
+            (start, start.checked_add_signed(Duration::days(7))?)
+        }
+        QuotaPeriod::Monthly => {
+            let start = NaiveDate::from_ymd_opt(date.year(), date.month(), 1)?;
+            let end = if date.month() == 12 {
+                NaiveDate::from_ymd_opt(date.year() + 1, 1, 1)?
+            } else {
+                NaiveDate::from_ymd_opt(date.year(), date.month() + 1, 1)?
+            };
+            (start, end)
+        }
+    };
+
+    Some((
+        start.and_hms(0, 0, 0).timestamp(),
+        end.and_hms(0, 0, 0).timestamp(),
+    ))
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct QuotaEntry {
+    pub user_id: u64,
+    pub name: String,
+    pub rank: String,
+    pub events: u32,
+    pub points: i32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct QuotaReport {
+    pub rule: QuotaRule,
+    pub start: i64,
+    pub end: i64,
+    pub passed: Vec<QuotaEntry>,
+    pub failed: Vec<QuotaEntry>,
+}
+
+fn rule_applies(rule: &QuotaRule, user: &User) -> bool {
+    let rank_matches = rule.ranks.is_empty() || rule.ranks.contains(&user.rank);
+    let division_matches = rule.divisions.is_empty()
+        || rule.divisions.iter().any(|division| {
+            user.divisions
+                .as_ref()
+                .is_some_and(|divisions| divisions.rank_in(*division).is_some())
+        });
+
+    rank_matches && division_matches
+}
+
+/// Events are the registered events starting inside the period that list the user as an attendee
+pub fn evaluate_quota(
+    rule: &QuotaRule,
+    users: &[User],
+    events: &[Event],
+    start: i64,
+    end: i64,
+) -> QuotaReport {
+    let mut passed = vec![];
+    let mut failed = vec![];
+    let events: Vec<&Event> = events
+        .iter()
+        .filter(|event| event.unix_start() >= start && event.unix_start() < end)
+        .collect();
+
+    for user in users.iter().filter(|user| rule_applies(rule, user)) {
+        let mut entry = QuotaEntry {
+            user_id: user.user_id,
+            name: user.name.clone(),
+            rank: user.rank.clone(),
+            events: events
+                .iter()
+                .filter(|event| event.attended(user.user_id))
+                .count() as u32,
+            points: 0,
+        };
+        for log in user.standing_logs() {
+            if log
+                .unix_time()
+                .is_some_and(|time| time >= start && time < end)
+            {
+                entry.points += log.amount;
+            }
+        }
+
+        if entry.events >= rule.min_events && entry.points >= rule.min_points {
+            passed.push(entry);
+        } else {
+            failed.push(entry);
+        }
+    }
+
+    passed.sort_by(|a, b| a.name.cmp(&b.name));
+    failed.sort_by(|a, b| a.name.cmp(&b.name));
+
+    QuotaReport {
+        rule: rule.clone(),
+        start,
+        end,
+        passed,
+        failed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-01-01 00:00:00 UTC, a Monday
+    const JAN_1_2024: i64 = 1_704_067_200;
+    const DAY: i64 = 86_400;
+
+    #[test]
+    fn week_runs_monday_to_monday() {
+        let wednesday_noon = JAN_1_2024 + 2 * DAY + DAY / 2;
+
+        assert_eq!(
+            period_bounds(QuotaPeriod::Weekly, wednesday_noon),
+            Some((JAN_1_2024, JAN_1_2024 + 7 * DAY))
+        );
+        assert_eq!(
+            period_bounds(QuotaPeriod::Weekly, JAN_1_2024 + 7 * DAY),
+            Some((JAN_1_2024 + 7 * DAY, JAN_1_2024 + 14 * DAY))
+        );
+    }
+
+    #[test]
+    fn month_rolls_over_the_year() {
+        let dec_1_2023 = JAN_1_2024 - 31 * DAY;
+
+        assert_eq!(
+            period_bounds(QuotaPeriod::Monthly, dec_1_2023 + 14 * DAY),
+            Some((dec_1_2023, JAN_1_2024))
+        );
+        assert_eq!(
+            period_bounds(QuotaPeriod::Monthly, JAN_1_2024 - 1),
+            Some((dec_1_2023, JAN_1_2024))
+        );
+    }
+
+    #[test]
+    fn out_of_range_time_has_no_period() {
+        assert_eq!(period_bounds(QuotaPeriod::Weekly, i64::MAX), None);
+        assert_eq!(period_bounds(QuotaPeriod::Monthly, i64::MIN), None);
+    }
+}
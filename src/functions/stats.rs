@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::definitions::{events::Event, users::User};
+
+/// Inclusive range of unix seconds, either end can be left open
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub struct TimeWindow {
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: i64) -> bool {
+        self.since.is_none_or(|since| time >= since) && self.until.is_none_or(|until| time <= until)
+    }
+}
+
+/// What one officer did inside a time window
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct OfficerStats {
+    pub admin_id: u64,
+    pub events_hosted: u64,
+    pub total_attendance: u64,
+    pub average_attendance: f64,
+    /// bP log entries written by the officer, reverted ones excluded
+    pub awards: u64,
+    pub points_awarded: i64,
+}
+
+/// Aggregates hosted events and bP logs per officer. Reverts and the awards they cancel
+/// are left out so a fat-fingered award doesn't count for or against anyone
+pub fn officer_stats(users: &[User], events: &[Event], window: TimeWindow) -> Vec<OfficerStats> {
+    let mut stats: HashMap<u64, OfficerStats> = HashMap::new();
+
+    for event in events.iter().filter(|e| window.contains(e.unix_start())) {
+        let entry = stats.entry(event.host_id).or_default();
+        entry.events_hosted += 1;
+        entry.total_attendance += event.attendees.len() as u64;
+    }
+
+    for user in users {
+        for log in user.standing_logs() {
+            if log.unix_time().is_some_and(|time| window.contains(time)) {
+                let entry = stats.entry(log.awarder).or_default();
+                entry.awards += 1;
+                entry.points_awarded += log.amount as i64;
+            }
+        }
+    }
+
+    stats
+        .into_iter()
+        .map(|(admin_id, mut entry)| {
+            entry.admin_id = admin_id;
+            if entry.events_hosted > 0 {
+                entry.average_attendance =
+                    entry.total_attendance as f64 / entry.events_hosted as f64;
+            }
+            entry
+        })
+        .collect()
+}
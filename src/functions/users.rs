@@ -1,34 +1,50 @@
+use crate::config::TotalPointsPolicy;
+use crate::logs::log_error;
 use crate::roblox::{get_user_info_from_id, UsernameResponse};
-use firebase_realtime_database::Database;
+use crate::storage::{StorageError, UserStore};
+use crate::AppState;
 use log::info;
 use tokio::join;
 
-use crate::definitions::ranks::{Ranks, STRanks, SableRanks};
-use crate::definitions::users::{Divisions, User, SABLE_ID, ST_ID, WIJ_ID};
-use crate::functions::promotion::get_required_points;
+use crate::definitions::ranks::{RankLadder, STRanks, SableRanks};
+use crate::definitions::users::{Divisions, User};
 use crate::roblox::get_rank_in_group;
 
-fn parse_rank(rank_result: Result<Option<u64>, reqwest::Error>) -> Option<u64> {
-    match rank_result {
-        Ok(rank) => rank,
-        Err(_) => {
-            return None;
-        }
+/// bP floor of the rank and the bP needed for the next one
+/// Points are lifted to the rank's floor, except at the prestige rank where the floor
+/// is the prestige threshold and lifting would prestige the user on every check
+fn lifted_points(rank: &str, points: i32, required: Option<i32>, app_state: &AppState) -> i32 {
+    match required {
+        Some(required) if rank != app_state.config.prestige.rank => points.max(required),
+        _ => points,
     }
 }
 
-pub async fn create_user_from_id(roblox_id: u64) -> Option<User> {
-    let (user_info_result, ranks) = join!(get_user_info_from_id(roblox_id), get_ranks(roblox_id),);
+fn rank_points(rank: &str, ladder: &RankLadder) -> (Option<i32>, Option<i32>) {
+    let goal_points = ladder
+        .next(rank)
+        .and_then(|next| ladder.required_points(&next.name));
+
+    (ladder.required_points(rank), goal_points)
+}
+
+/// Builds a new user from roblox. `Ok(None)` means roblox answered and they have no rank
+/// in the main group, an `Err` means roblox couldn't be asked
+pub async fn create_user_from_id(
+    roblox_id: u64,
+    app_state: &AppState,
+) -> Result<Option<User>, reqwest::Error> {
+    let (user_info_result, ranks) = join!(
+        get_user_info_from_id(roblox_id),
+        get_ranks(roblox_id, app_state),
+    );
     info!("{:?} {:?}", user_info_result, ranks);
 
-    let user_info: UsernameResponse = match user_info_result {
-        Ok(info) => info,
-        Err(_) => return None,
-    };
+    let (rank, st_rank, sable_rank) = ranks?;
 
-    let (rank, st_rank, sable_rank) = ranks;
+    if let Some(rank_name) = rank {
+        let user_info: UsernameResponse = user_info_result?;
 
-    if let Some(rank_enum) = rank {
         let mut divisions = None;
         if st_rank.is_some() || sable_rank.is_some() {
             divisions = Some(Divisions {
@@ -37,60 +53,69 @@ pub async fn create_user_from_id(roblox_id: u64) -> Option<User> {
             });
         }
 
-        let goal_points = match rank_enum.get_next() {
-            Some(rank) => get_required_points(rank),
-            None => None,
-        };
-
-        let required_points = get_required_points(rank_enum.clone());
+        let (required_points, goal_points) = rank_points(&rank_name, &app_state.ladder());
+        let points = lifted_points(&rank_name, 0, required_points, app_state);
 
         let user_struct = User {
             user_id: roblox_id,
             name: user_info.name,
-            points: {
-                if required_points.is_some() {
-                    required_points.unwrap()
-                } else {
-                    0
-                }
-            },
-            total_points: {
-                if required_points.is_some() {
-                    required_points.unwrap()
-                } else {
-                    0
-                }
-            },
+            points,
+            total_points: points,
             floor_points: required_points,
             goal_points,
-            rank: rank_enum,
+            rank: rank_name,
             events: 0,
             divisions,
 
             prestige: None,
 
             bp_logs: None,
+
+            loa: None,
+            inactivity_handled: None,
         };
 
-        return Some(user_struct);
+        return Ok(Some(user_struct));
     }
 
-    None
+    Ok(None)
 }
 
-pub async fn get_ranks(roblox_id: u64) -> (Option<Ranks>, Option<STRanks>, Option<SableRanks>) {
+pub async fn get_ranks(
+    roblox_id: u64,
+    app_state: &AppState,
+) -> Result<(Option<String>, Option<STRanks>, Option<SableRanks>), reqwest::Error> {
+    let groups = &app_state.config.groups;
     let (main_group_result, st_result, sable_result) = join!(
-        get_rank_in_group(WIJ_ID, roblox_id),
-        get_rank_in_group(ST_ID, roblox_id),
-        get_rank_in_group(SABLE_ID, roblox_id)
+        get_rank_in_group(groups.wij, roblox_id),
+        get_rank_in_group(groups.st, roblox_id),
+        get_rank_in_group(groups.sable, roblox_id)
     );
 
-    let main_group_rank = parse_rank(main_group_result);
-    let st_rank_option = parse_rank(st_result);
-    let sable_rank_option = parse_rank(sable_result);
+    let main_group_rank = main_group_result?;
+    let st_rank_option = st_result?;
+    let sable_rank_option = sable_result?;
 
+    // A group role missing from the ladder counts as not in the group rather than an
+    // unnamed rank, the ladder needs updating before they can be tracked
     let rank = match main_group_rank {
-        Some(rank) => Ranks::from_value(rank),
+        Some(value) => {
+            let rank = app_state
+                .ladder()
+                .by_value(value)
+                .map(|rank| rank.name.clone());
+            if rank.is_none() {
+                log_error(
+                    &app_state.config.webhooks,
+                    format!(
+                        "{} has group rank value {} which isn't in the rank ladder",
+                        roblox_id, value
+                    ),
+                )
+                .await;
+            }
+            rank
+        }
         None => None,
     };
 
@@ -104,14 +129,25 @@ pub async fn get_ranks(roblox_id: u64) -> (Option<Ranks>, Option<STRanks>, Optio
         None => None,
     };
 
-    (rank, st_rank, sable_rank)
+    Ok((rank, st_rank, sable_rank))
 }
 
-pub async fn reconcile_user(user: &mut User, database: &Database) {
-    let (ranks, user_info) = join!(get_ranks(user.user_id), get_user_info_from_id(user.user_id));
-    let (rank, st_rank, sable_rank) = ranks;
+/// Pulls the user's current ranks and name from roblox without saving anything.
+/// Returns false if they're no longer in the main group or roblox couldn't be reached
+pub async fn refresh_user(user: &mut User, app_state: &AppState) -> bool {
+    let (ranks, user_info) = join!(
+        get_ranks(user.user_id, app_state),
+        get_user_info_from_id(user.user_id)
+    );
+    let (rank, st_rank, sable_rank) = match ranks {
+        Ok(ranks) => ranks,
+        Err(e) => {
+            info!("failed to refresh ranks for {}: {}", user.user_id, e);
+            return false;
+        }
+    };
 
-    if let Some(rank_enum) = rank {
+    if let Some(rank_name) = rank {
         let mut divisions = None;
         if st_rank.is_some() || sable_rank.is_some() {
             divisions = Some(Divisions {
@@ -120,28 +156,60 @@ pub async fn reconcile_user(user: &mut User, database: &Database) {
             });
         }
 
-        let goal_points = match rank_enum.get_next() {
-            Some(rank) => get_required_points(rank),
-            None => None,
-        };
-
-        let required_points = get_required_points(rank_enum.clone());
+        let (required_points, goal_points) = rank_points(&rank_name, &app_state.ladder());
 
-        if required_points.is_some() && user.points < required_points.unwrap() {
-            user.points = required_points.unwrap();
-        }
+        user.points = lifted_points(&rank_name, user.points, required_points, app_state);
 
         user.floor_points = required_points;
         user.goal_points = goal_points;
-        user.rank = rank_enum;
+        user.rank = rank_name;
         user.divisions = divisions;
 
-        if user_info.is_ok() {
-            user.name = user_info.unwrap().name;
+        if let Ok(info) = user_info {
+            user.name = info.name;
         }
 
-        let _create_result = database
-            .put(format!("users/{}", user.user_id).as_str(), &user)
-            .await;
+        return true;
     }
+
+    false
+}
+
+pub async fn reconcile_user(user: &mut User, app_state: &AppState) {
+    if !refresh_user(user, app_state).await {
+        return;
+    }
+
+    if let Err(e) = app_state.store.put_user(user).await {
+        info!("failed to save reconciled user {}: {}", user.user_id, e);
+    }
+}
+
+/// Recomputes every user's `total_points` from their bP logs plus the points they were
+/// lifted to by their rank floor, returns how many changed
+pub async fn backfill_total_points(
+    store: &dyn UserStore,
+    policy: TotalPointsPolicy,
+) -> Result<usize, StorageError> {
+    let mut changed = 0;
+    for mut user in store.get_users().await? {
+        let total = user.logged_total(policy) + user.unlogged_points();
+        if total != user.total_points {
+            user.total_points = total;
+            store.put_user(&user).await?;
+            changed += 1;
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Every user, with log ids filled in so reverts can be matched against legacy entries
+pub async fn get_users_with_log_ids(store: &dyn UserStore) -> Result<Vec<User>, StorageError> {
+    let mut users = store.get_users().await?;
+    for user in users.iter_mut() {
+        user.ensure_log_ids();
+    }
+
+    Ok(users)
 }
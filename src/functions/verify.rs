@@ -1,65 +1,150 @@
-use std::collections::HashMap;
-use std::time::SystemTime;
+use std::{fmt, time::SystemTime};
 
-use firebase_realtime_database::Database;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use rand::Rng;
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct VerificationBody {
-    pub discord_id: String,
-    pub creation_time: SystemTime,
+use crate::{
+    definitions::verification::{LinkAction, LinkRecord, VerifiedStruct},
+    storage::{StorageError, VerificationStore},
+};
+
+/// No 0/O or 1/I so codes survive being read off a screen and typed in game
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LEN: usize = 6;
+
+pub fn generate_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..CODE_LEN)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect()
 }
 
-pub async fn get_verification_map(
-    database: &Database,
-) -> Option<HashMap<String, VerificationBody>> {
-    let awaiting_result = database.get("verification/awaiting/").await;
-
-    if let Ok(response) = awaiting_result {
-        let result = response
-            .json::<Option<HashMap<String, VerificationBody>>>()
-            .await;
-
-        if result.is_err() {
-            return None;
-        } else {
-            let user_map_option = result.unwrap();
-            return user_map_option;
+/// A code no request has used yet, so a collision can't overwrite someone else's request
+pub async fn unused_code(store: &dyn VerificationStore) -> Result<String, StorageError> {
+    loop {
+        let code = generate_code();
+        if store.get_awaiting(&code).await?.is_none() {
+            return Ok(code);
         }
     }
+}
+
+/// Codes are matched case-insensitively and ignoring surrounding whitespace
+pub fn normalize_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
 
-    None
+#[derive(Debug)]
+pub enum LinkError {
+    /// The roblox account is already linked to another discord id
+    RobloxTaken(VerifiedStruct),
+    Storage(StorageError),
 }
 
-pub async fn get_verification_body<T: DeserializeOwned>(
-    path: &str,
-    database: &Database,
-) -> Option<T> {
-    let verification_result = database.get(path).await;
-    if verification_result.is_err() {
-        return None;
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::RobloxTaken(verified) => write!(
+                f,
+                "roblox account {} is already linked to {}",
+                verified.roblox_id, verified.discord_id
+            ),
+            LinkError::Storage(e) => write!(f, "{}", e),
+        }
     }
+}
 
-    let verification_response = verification_result.unwrap();
-    let verification_body_result = verification_response.json::<T>().await;
-    if verification_body_result.is_err() {
-        return None;
+impl From<StorageError> for LinkError {
+    fn from(err: StorageError) -> Self {
+        LinkError::Storage(err)
     }
+}
 
-    let verification_struct = verification_body_result.unwrap();
-    Some(verification_struct)
+fn link_record(
+    verified: &VerifiedStruct,
+    action: LinkAction,
+    api_key: &str,
+    reason: Option<&str>,
+) -> LinkRecord {
+    LinkRecord {
+        discord_id: verified.discord_id.clone(),
+        roblox_id: verified.roblox_id,
+        action,
+        time: SystemTime::now(),
+        api_key: api_key.to_string(),
+        reason: reason.map(str::to_string),
+    }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
-pub struct VerifiedStruct {
-    pub roblox_id: u64,
-    pub discord_id: String,
+/// Links `discord_id` to `roblox_id`, replacing any account the discord id was linked to before.
+/// Each roblox account can only be linked to one discord id. Changes are added to the link history
+pub async fn link_accounts(
+    store: &dyn VerificationStore,
+    discord_id: &str,
+    roblox_id: u64,
+    api_key: &str,
+    reason: Option<&str>,
+) -> Result<VerifiedStruct, LinkError> {
+    let verified = VerifiedStruct {
+        roblox_id,
+        discord_id: discord_id.to_string(),
+    };
+    let previous = store.get_verified(discord_id).await?;
+    if let Some(previous) = &previous {
+        if previous.roblox_id == roblox_id {
+            return Ok(previous.clone());
+        }
+    }
+
+    // the store enforces one discord id per roblox account, so this is safe against races
+    match store.put_verified(&verified).await {
+        Ok(()) => {}
+        Err(StorageError::Conflict(e)) => {
+            return Err(match store.get_verified_by_roblox(roblox_id).await? {
+                Some(existing) => LinkError::RobloxTaken(existing),
+                None => LinkError::Storage(StorageError::Conflict(e)),
+            })
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    if let Some(previous) = &previous {
+        store
+            .add_link_record(&link_record(
+                previous,
+                LinkAction::Unlinked,
+                api_key,
+                reason,
+            ))
+            .await?;
+    }
+    store
+        .add_link_record(&link_record(&verified, LinkAction::Linked, api_key, reason))
+        .await?;
+
+    Ok(verified)
 }
 
-pub async fn is_verified(discord_user_id: String, database: &Database) -> Option<VerifiedStruct> {
-    get_verification_body::<VerifiedStruct>(
-        format!("verification/discord/{}", discord_user_id).as_str(),
-        database,
-    )
-    .await
+/// Removes the link for `discord_id`, returning what it pointed to
+pub async fn unlink_account(
+    store: &dyn VerificationStore,
+    discord_id: &str,
+    api_key: &str,
+    reason: Option<&str>,
+) -> Result<Option<VerifiedStruct>, StorageError> {
+    let previous = match store.get_verified(discord_id).await? {
+        Some(previous) => previous,
+        None => return Ok(None),
+    };
+
+    store.delete_verified(discord_id).await?;
+    store
+        .add_link_record(&link_record(
+            &previous,
+            LinkAction::Unlinked,
+            api_key,
+            reason,
+        ))
+        .await?;
+
+    Ok(Some(previous))
 }
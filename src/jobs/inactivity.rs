@@ -0,0 +1,106 @@
+use std::time::SystemTime;
+
+use chrono::NaiveDateTime;
+use log::info;
+
+use crate::{
+    config::InactivityAction,
+    functions::{
+        inactivity::{apply_inactivity, find_inactive, mark_flagged, InactiveMember},
+        users::get_users_with_log_ids,
+    },
+    logs::{log_error, log_to_discord},
+    AppState,
+};
+
+/// Discord rejects messages over 2000 characters
+const MAX_MESSAGE_LEN: usize = 1900;
+
+fn format_members(header: String, members: &[InactiveMember]) -> String {
+    let mut message = header;
+    for member in members {
+        let last_active = NaiveDateTime::from_timestamp(member.last_active, 0).format("%Y-%m-%d");
+        let line = match &member.target_rank {
+            Some(target) => format!(
+                "\n{} - {} ({} → {}), last active {}",
+                member.user_id, member.name, member.rank, target, last_active
+            ),
+            None => format!(
+                "\n{} - {} ({}), last active {}",
+                member.user_id, member.name, member.rank, last_active
+            ),
+        };
+        if message.len() + line.len() > MAX_MESSAGE_LEN {
+            message += "\n...";
+            break;
+        }
+        message += &line;
+    }
+
+    message
+}
+
+/// Flags inactive members, and ranks them down unless the job is in dry-run mode
+pub async fn check_inactivity(app_state: &AppState) {
+    let config = &app_state.config.inactivity;
+    let users = match get_users_with_log_ids(app_state.store.as_ref()).await {
+        Ok(users) => users,
+        Err(e) => {
+            info!("inactivity check failed to read users: {}", e);
+            return;
+        }
+    };
+
+    let members = find_inactive(&users, &app_state.ladder(), config, SystemTime::now());
+    if members.is_empty() {
+        return;
+    }
+
+    if config.dry_run || config.action == InactivityAction::Flag {
+        let header = if config.action == InactivityAction::Flag {
+            format!(
+                "{} members inactive for {}+ weeks:",
+                members.len(),
+                config.weeks
+            )
+        } else {
+            format!(
+                "[dry run] {} members inactive for {}+ weeks would be ranked:",
+                members.len(),
+                config.weeks
+            )
+        };
+        log_to_discord(&app_state.config.webhooks, format_members(header, &members)).await;
+        // a dry run writes nothing, so it keeps previewing the same members
+        if !config.dry_run {
+            mark_flagged(&members, app_state, "inactivity").await;
+        }
+        return;
+    }
+
+    if !app_state.ladder_in_sync() {
+        log_error(
+            &app_state.config.webhooks,
+            "Rank ladder is out of sync with the group, skipping inactivity rank changes"
+                .to_string(),
+        )
+        .await;
+        return;
+    }
+
+    let mut roblox_user = app_state.roblox_user.lock().await;
+    let ranked = apply_inactivity(&members, app_state, &mut roblox_user, "inactivity").await;
+    log_to_discord(
+        &app_state.config.webhooks,
+        format_members(
+            format!(
+                "Ranked {} of {} members inactive for {}+ weeks:",
+                ranked,
+                members.len(),
+                config.weeks
+            ),
+            &members,
+        ),
+    )
+    .await;
+}
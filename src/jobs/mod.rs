@@ -1,18 +1,57 @@
-use firebase_realtime_database::Database;
-use std::{thread, time::Duration};
-use tokio::task;
+use std::{sync::Arc, time::Duration};
+use tokio::{task, time};
 
+use crate::AppState;
+
+mod inactivity;
+mod quota_report;
+pub mod role_sync;
 mod verify_key_cleanup;
 
-static THREAD_DELAY: u64 = 30000;
+pub fn start_jobs(app_state: Arc<AppState>) {
+    let jobs = app_state.config.jobs.clone();
+
+    let cleanup_state = app_state.clone();
+    task::spawn(async move {
+        loop {
+            verify_key_cleanup::key_cleanup(
+                cleanup_state.store.as_ref(),
+                jobs.verification_timeout_ms,
+                &cleanup_state.config.webhooks,
+            )
+            .await;
+
+            time::sleep(Duration::from_millis(jobs.interval_ms)).await;
+        }
+    });
+
+    let quota_state = app_state.clone();
+    task::spawn(async move {
+        let mut periods = quota_report::current_periods(&quota_state.config.quotas);
+        loop {
+            time::sleep(Duration::from_millis(jobs.interval_ms)).await;
+
+            quota_report::post_finished_reports(&quota_state, &mut periods).await;
+        }
+    });
+
+    if app_state.config.inactivity.enabled {
+        let inactivity_state = app_state.clone();
+        let check_secs = app_state.config.inactivity.check_secs;
+        task::spawn(async move {
+            loop {
+                time::sleep(Duration::from_secs(check_secs)).await;
+
+                inactivity::check_inactivity(&inactivity_state).await;
+            }
+        });
+    }
 
-pub fn start_jobs(database: Database) {
     task::spawn(async move {
-        let db = database;
         loop {
-            verify_key_cleanup::key_cleanup(&db).await;
+            time::sleep(Duration::from_secs(jobs.role_sync_secs)).await;
 
-            thread::sleep(Duration::from_millis(THREAD_DELAY));
+            role_sync::sync_roles(&app_state).await;
         }
     });
 }
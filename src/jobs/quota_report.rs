@@ -0,0 +1,116 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::NaiveDateTime;
+use log::info;
+
+use crate::{
+    config::QuotaRule,
+    functions::{
+        quotas::{evaluate_quota, period_bounds, QuotaReport},
+        users::get_users_with_log_ids,
+    },
+    logs::log_to_discord,
+    AppState,
+};
+
+/// Discord rejects messages over 2000 characters
+const MAX_MESSAGE_LEN: usize = 1900;
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+fn format_date(time: i64) -> String {
+    NaiveDateTime::from_timestamp(time, 0)
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+fn format_report(report: &QuotaReport) -> String {
+    let mut message = format!(
+        "**{}** quota for {} to {}: {} passed, {} failed",
+        report.rule.name,
+        format_date(report.start),
+        format_date(report.end - 1),
+        report.passed.len(),
+        report.failed.len()
+    );
+
+    for entry in report.failed.iter() {
+        let line = format!(
+            "\n{} - {} ({}): {} events, {} bP",
+            entry.user_id, entry.name, entry.rank, entry.events, entry.points
+        );
+        if message.len() + line.len() > MAX_MESSAGE_LEN {
+            message += "\n...";
+            break;
+        }
+        message += &line;
+    }
+
+    message
+}
+
+/// Start of the current period of every rule, reports are only posted once that moves on.
+/// Periods that end while the server is down are not reported
+pub fn current_periods(rules: &[QuotaRule]) -> HashMap<String, i64> {
+    let now = now();
+    rules
+        .iter()
+        .filter_map(|rule| Some((rule.name.clone(), period_bounds(rule.period, now)?.0)))
+        .collect()
+}
+
+/// Posts the report of every period that ended since the last check
+pub async fn post_finished_reports(app_state: &AppState, periods: &mut HashMap<String, i64>) {
+    let now = now();
+    let finished: Vec<(&QuotaRule, i64)> = app_state
+        .config
+        .quotas
+        .iter()
+        .filter_map(|rule| {
+            let (current, _) = period_bounds(rule.period, now)?;
+            match periods.get(&rule.name) {
+                Some(&previous) if previous < current => Some((rule, previous)),
+                _ => None,
+            }
+        })
+        .collect();
+    if finished.is_empty() {
+        return;
+    }
+
+    let users = match get_users_with_log_ids(app_state.store.as_ref()).await {
+        Ok(users) => users,
+        Err(e) => {
+            info!("quota report failed to read users: {}", e);
+            return;
+        }
+    };
+    let events = match app_state.store.get_events().await {
+        Ok(events) => events,
+        Err(e) => {
+            info!("quota report failed to read events: {}", e);
+            return;
+        }
+    };
+
+    for (rule, previous) in finished {
+        let (start, end) = match period_bounds(rule.period, previous) {
+            Some(bounds) => bounds,
+            None => continue,
+        };
+        let report = evaluate_quota(rule, &users, &events, start, end);
+        log_to_discord(&app_state.config.webhooks, format_report(&report)).await;
+
+        if let Some((current, _)) = period_bounds(rule.period, now) {
+            periods.insert(rule.name.clone(), current);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::sync::{atomic::Ordering, Arc};
+
+use log::info;
+
+use crate::{
+    logs::{log_error, log_to_discord},
+    roblox::get_group_roles,
+    AppState,
+};
+
+/// Checks the rank ladder against the main group's roles, fixing recreated roles
+/// and pausing automatic promotion while anything else disagrees
+pub async fn sync_roles(app_state: &AppState) {
+    let roles = match get_group_roles(app_state.config.groups.wij).await {
+        Ok(roles) => roles,
+        Err(e) => {
+            info!("role sync failed to fetch group roles: {}", e);
+            return;
+        }
+    };
+
+    let mut ladder = (*app_state.ladder()).clone();
+    let sync = ladder.sync_roles(&roles);
+
+    if !sync.updated.is_empty() {
+        *app_state.ranks.write() = Arc::new(ladder);
+        log_to_discord(
+            &app_state.config.webhooks,
+            format!(
+                "Updated role ids from the group:\n{}",
+                sync.updated.join("\n")
+            ),
+        )
+        .await;
+    }
+
+    let in_sync = sync.problems.is_empty();
+    let was_in_sync = app_state.ladder_in_sync.swap(in_sync, Ordering::Relaxed);
+    if !in_sync {
+        log_error(
+            &app_state.config.webhooks,
+            format!(
+                "Rank ladder does not match the group, automatic promotion is paused:\n{}",
+                sync.problems.join("\n")
+            ),
+        )
+        .await;
+    } else if !was_in_sync {
+        log_error(
+            &app_state.config.webhooks,
+            "Rank ladder matches the group again, automatic promotion resumed".to_string(),
+        )
+        .await;
+    }
+}
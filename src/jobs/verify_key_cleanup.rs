@@ -1,30 +1,44 @@
-use std::time::{Duration, SystemTime};
+use std::time::SystemTime;
 
-use firebase_realtime_database::Database;
+use log::info;
 
-use crate::{functions::verify::get_verification_map, logs::log_error};
+use crate::{
+    config::WebhookConfig, definitions::verification::VerificationState, logs::log_error,
+    storage::VerificationStore,
+};
 
-static CLEANUP_TIMEOUT: u64 = 60000 * 5; // five minutes
-
-pub async fn key_cleanup(db: &Database) {
-    let user_map_option = get_verification_map(&db).await;
+/// Marks pending codes past `timeout_ms` as expired, then deletes expired and revoked codes
+/// once they're another `timeout_ms` old. Keeping them around for a while lets the game
+/// tell a late player their code expired instead of that it doesn't exist
+pub async fn key_cleanup(store: &dyn VerificationStore, timeout_ms: u64, webhooks: &WebhookConfig) {
+    let user_map = match store.get_awaiting_map().await {
+        Ok(map) => map,
+        Err(e) => {
+            info!("verification cleanup failed to read awaiting map: {}", e);
+            return;
+        }
+    };
 
-    if let Some(user_map) = user_map_option {
-        for (key, user) in user_map {
-            let current_time = SystemTime::now();
-            let time_between_bool = current_time.duration_since(user.creation_time).unwrap()
-                >= Duration::from_millis(CLEANUP_TIMEOUT);
-            if time_between_bool {
-                let delete_response = db
-                    .delete(format!("verification/awaiting/{}", key).as_str())
-                    .await;
+    let current_time = SystemTime::now();
+    for (key, mut user) in user_map {
+        match user.state {
+            VerificationState::Pending if user.is_expired(timeout_ms, current_time) => {
+                user.state = VerificationState::Expired;
+                if let Err(e) = store.put_awaiting(&key, &user).await {
+                    log_error(webhooks, format!("{}", e)).await;
+                }
+            }
+            VerificationState::Pending => {}
+            _ if user.is_expired(timeout_ms.saturating_mul(2), current_time) => {
+                let delete_response = store.delete_awaiting(&key).await;
 
                 if let Err(e) = delete_response {
-                    log_error(format!("{:?}", e)).await;
-                } else if let Ok(_) = delete_response {
+                    log_error(webhooks, format!("{}", e)).await;
+                } else {
                     println!("deleted code for user {}", user.discord_id);
                 }
             }
+            _ => {}
         }
     }
 }
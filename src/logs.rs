@@ -1,17 +1,14 @@
 use reqwest::Client;
 use serde::Serialize;
-use std::{fs::File, io::Read};
+
+use crate::config::WebhookConfig;
 
 #[derive(Serialize, Debug)]
 struct WebhookBody {
     content: String,
 }
 
-pub async fn log_to_discord(message: String) {
-    let mut webhook_file = File::open("webhook.txt").expect("file to be able to open");
-    let mut webhook = String::new();
-    webhook_file.read_to_string(&mut webhook).unwrap();
-
+async fn post_webhook(webhook: &str, message: String) {
     let client = Client::new();
     let _response = client
         .post(webhook)
@@ -20,15 +17,11 @@ pub async fn log_to_discord(message: String) {
         .await;
 }
 
-pub async fn log_error(message: String) {
-    let mut webhook_file = File::open("error-webhook.txt").expect("file to be able to open");
-    let mut webhook = String::new();
-    webhook_file.read_to_string(&mut webhook).unwrap();
+/// Webhooks come from `AppState::config`, see `WebhookConfig`
+pub async fn log_to_discord(webhooks: &WebhookConfig, message: String) {
+    post_webhook(&webhooks.log, message).await;
+}
 
-    let client = Client::new();
-    let _response = client
-        .post(webhook)
-        .json(&WebhookBody { content: message })
-        .send()
-        .await;
+pub async fn log_error(webhooks: &WebhookConfig, message: String) {
+    post_webhook(&webhooks.error, message).await;
 }
@@ -1,61 +1,154 @@
+mod auth;
+mod config;
 mod definitions;
 mod functions;
 mod jobs;
 mod logs;
 mod roblox;
 mod routes;
+mod signing;
+mod storage;
+#[cfg(test)]
+mod test_support;
 
 use actix_web::middleware::{self, Logger};
 use actix_web::{get, web, App, HttpServer};
-use anyhow;
-use definitions::global_state::{AppState, Leaderboard};
+use anyhow::anyhow;
+use auth::ApiKeyStore;
+use config::{Config, StorageBackend};
+use definitions::global_state::{AppState, Leaderboard, PeriodLeaderboard};
+use definitions::ranks::RankLadder;
 use env_logger::Env;
 use firebase_realtime_database::Database;
-use functions::lb::write_users;
+use functions::users::backfill_total_points;
 use parking_lot::RwLock;
 use routes::configure_routes;
+use signing::RequestSigner;
+use storage::{import::import_firebase_export, FirebaseStore, MemoryStore, SqliteStore, Storage};
+use tokio::sync::Mutex;
 
-use std::{fs::File, io::Read};
+use std::{
+    env,
+    fs::File,
+    io::Read,
+    sync::{atomic::AtomicBool, Arc},
+};
 
 #[get("/")]
 async fn index() -> String {
-    format!("wAVE mainframe backend extension!")
+    "wAVE mainframe backend extension!".to_string()
+}
+
+fn open_sqlite(config: &Config) -> anyhow::Result<SqliteStore> {
+    let path = config
+        .storage
+        .sqlite_path
+        .as_ref()
+        .ok_or_else(|| anyhow!("storage.sqlite_path is not set"))?;
+    Ok(SqliteStore::open(path)?)
+}
+
+fn create_store(config: &Config) -> anyhow::Result<Arc<dyn Storage>> {
+    match config.storage.backend {
+        StorageBackend::Memory => Ok(Arc::new(MemoryStore::new())),
+        StorageBackend::Sqlite => Ok(Arc::new(open_sqlite(config)?)),
+        StorageBackend::Firebase => {
+            // both are checked by Config::validate
+            let project_id = config.storage.firebase_project_id.as_deref().unwrap();
+            let key_path = config.storage.firebase_key_path.as_deref().unwrap();
+            let database = Database::from_path(project_id, key_path)?;
+            Ok(Arc::new(FirebaseStore::new(database)))
+        }
+    }
 }
 
 #[actix_web::main]
 async fn main() -> anyhow::Result<()> {
-    let mut cookie_file = File::open("wij-games-cookie.txt")?;
+    let config = Config::load()?;
+
+    let args: Vec<String> = env::args().collect();
+    if args.len() == 3 && args[1] == "import-firebase" {
+        let store = open_sqlite(&config)?;
+        let summary = import_firebase_export(&args[2], &store)?;
+        println!(
+            "imported {} users, {} pending and {} completed verifications",
+            summary.users, summary.awaiting, summary.verified
+        );
+        if summary.duplicate_links > 0 {
+            println!(
+                "skipped {} links to already linked roblox accounts, see verification_history",
+                summary.duplicate_links
+            );
+        }
+        return Ok(());
+    }
+    if args.len() == 2 && args[1] == "backfill-total-points" {
+        let store = create_store(&config)?;
+        let changed = backfill_total_points(store.as_ref(), config.points.total_policy).await?;
+        println!("updated total_points for {} users", changed);
+        return Ok(());
+    }
+
+    config.validate_server_files()?;
+
+    let mut cookie_file = File::open(&config.roblox.cookie_path)?;
     let mut cookie = String::new();
     cookie_file.read_to_string(&mut cookie).unwrap();
 
-    let job_db = Database::from_path("wave-mainframe-default-rtdb", "firebase-key.json")?;
-    write_users(&job_db).await?;
-    jobs::start_jobs(job_db);
+    let store = create_store(&config)?;
+    let leaderboard_users = store.get_users_by_points().await?;
 
     env_logger::builder()
         .target(env_logger::Target::Stdout)
         .parse_env(Env::default().default_filter_or("info"))
         .init();
 
-    let user = roblox::create_user(cookie, true).await;
-    HttpServer::new(move || {
-        let main_db =
-            Database::from_path("wave-mainframe-default-rtdb", "firebase-key.json").unwrap();
-        let lb = Leaderboard::new();
+    let api_keys = ApiKeyStore::from_file(&config.auth.api_keys_path)?;
+    let signer = RequestSigner::from_file(
+        &config.auth.signing_secret_path,
+        config.auth.max_clock_skew_secs,
+    )?;
+    let ranks = RankLadder::from_file(&config.ranks.path)?;
+    let user = roblox::create_user(
+        cookie,
+        config.roblox.validate_cookie,
+        config.webhooks.clone(),
+    )
+    .await;
+    let bind_address = (config.server.bind_address.clone(), config.server.port);
+    let app_state = web::Data::new(AppState {
+        store,
+        roblox_user: Mutex::new(user),
+        leaderboard: RwLock::new(Leaderboard::new(
+            config.leaderboard.refresh_secs,
+            leaderboard_users,
+        )),
+        weekly_leaderboard: RwLock::new(PeriodLeaderboard::new(
+            config.leaderboard.weekly_refresh_secs,
+        )),
+        monthly_leaderboard: RwLock::new(PeriodLeaderboard::new(
+            config.leaderboard.monthly_refresh_secs,
+        )),
+        api_keys,
+        signer,
+        ranks: RwLock::new(Arc::new(ranks)),
+        ladder_in_sync: AtomicBool::new(true),
+        config,
+    });
 
+    jobs::role_sync::sync_roles(&app_state).await;
+    jobs::start_jobs(app_state.clone().into_inner());
+
+    HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
             .wrap(Logger::new("%a %{User-Agent}i"))
             .wrap(middleware::NormalizePath::trim())
-            .app_data(web::Data::new(AppState {
-                database: RwLock::new(main_db),
-                roblox_user: RwLock::new(user.clone()),
-                leaderboard: RwLock::new(lb),
-            }))
+            .app_data(app_state.clone())
             .service(index)
             .configure(configure_routes)
     })
-    .bind(("127.0.0.1", 8080))?
+    .bind(bind_address)?
     .run()
     .await?;
 
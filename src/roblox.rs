@@ -5,10 +5,9 @@ use std::{
     time::{Duration, Instant},
 };
 
-use reqwest;
 use serde::{Deserialize, Serialize};
 
-use crate::{definitions::ranks::Ranks, logs::log_error};
+use crate::{config::WebhookConfig, logs::log_error};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -51,7 +50,9 @@ pub struct GroupResponse {
 }
 
 pub async fn get_user_info_from_id(user_id: u64) -> Result<UsernameResponse, reqwest::Error> {
-    let response = reqwest::get(format!("https://users.roblox.com/v1/users/{}", user_id)).await?;
+    let response = reqwest::get(format!("https://users.roblox.com/v1/users/{}", user_id))
+        .await?
+        .error_for_status()?;
     let username_response = response.json::<UsernameResponse>().await?;
 
     Ok(username_response)
@@ -115,11 +116,12 @@ pub async fn get_rank_in_group(group_id: u64, user_id: u64) -> Result<Option<u64
         "https://groups.roblox.com/v2/users/{}/groups/roles",
         user_id
     ))
-    .await?;
+    .await?
+    // a rate limited or failed response must not read as "not in the group"
+    .error_for_status()?;
 
     let group_response = response.json::<GroupResponse>().await?;
-    if group_response.data.is_some() {
-        let data = group_response.data.unwrap();
+    if let Some(data) = group_response.data {
         let index = data
             .iter()
             .position(|group_info| group_info.group.id == group_id);
@@ -134,6 +136,29 @@ pub async fn get_rank_in_group(group_id: u64, user_id: u64) -> Result<Option<u64
     }
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct GroupRole {
+    pub id: u64,
+    pub name: String,
+    pub rank: u64,
+}
+
+#[derive(Deserialize)]
+struct GroupRolesResponse {
+    roles: Vec<GroupRole>,
+}
+
+pub async fn get_group_roles(group_id: u64) -> Result<Vec<GroupRole>, reqwest::Error> {
+    let response = reqwest::get(format!(
+        "https://groups.roblox.com/v1/groups/{}/roles",
+        group_id
+    ))
+    .await?;
+
+    let roles_response = response.json::<GroupRolesResponse>().await?;
+    Ok(roles_response.roles)
+}
+
 #[derive(Serialize)]
 pub struct LogoutBody {
     session: String,
@@ -151,12 +176,14 @@ pub struct RobloxAccount {
     headers: HashMap<String, String>,
     token: String,
     last_token_get: Option<Instant>,
+    webhooks: WebhookConfig,
 }
 
 impl RobloxAccount {
-    fn new(cookie: String) -> RobloxAccount {
+    fn new(cookie: String, webhooks: WebhookConfig) -> RobloxAccount {
         RobloxAccount {
             cookie,
+            webhooks,
             headers: HashMap::new(),
             token: String::new(),
             last_token_get: None,
@@ -193,23 +220,23 @@ impl RobloxAccount {
 
                     self.token = token.clone();
                     self.last_token_get = Some(Instant::now());
-                    return Some(token);
+                    Some(token)
                 } else {
-                    return None;
+                    None
                 }
             }
             Err(e) => {
-                log_error(format!("ERROR: {}", e.to_string())).await;
-                return None;
+                log_error(&self.webhooks, format!("ERROR: {}", e)).await;
+                None
             }
-        };
+        }
     }
 
     fn add_header(&mut self, header_name: &str, header_value: &str) {
         if self
             .headers
             .insert(header_name.to_string(), header_value.to_string())
-            == None
+            .is_none()
         {
             println!("failed to add header to hashmap");
             self.add_header(header_name, header_value);
@@ -222,15 +249,16 @@ impl RobloxAccount {
         &mut self,
         user_id: u64,
         group_id: u64,
-        rank: Ranks,
+        role_id: u64,
     ) -> Result<bool, reqwest::Error> {
         let mut token = self.token.clone();
-        if token == "" && self.last_token_get == None {
+        if token.is_empty() && self.last_token_get.is_none() {
             let potential_csrf_token = self.get_current_token().await;
             match potential_csrf_token {
                 Some(t) => token = t,
                 None => {
                     log_error(
+                        &self.webhooks,
                         "Failed to retrieve xCRSF token, is the roblox API down?".to_string(),
                     )
                     .await;
@@ -245,6 +273,7 @@ impl RobloxAccount {
                     Some(t) => token = t,
                     None => {
                         log_error(
+                            &self.webhooks,
                             "Failed to retrieve xCRSF token, is the roblox API down?".to_string(),
                         )
                         .await;
@@ -264,9 +293,7 @@ impl RobloxAccount {
             .header("Refer", "https://www.roblox.com")
             .header("cookie", format!(".ROBLOSECURITY={};", self.cookie))
             .header("X-CSRF-TOKEN", token)
-            .json(&SetRankBody {
-                role_id: rank.to_role_id(),
-            })
+            .json(&SetRankBody { role_id })
             .send()
             .await?;
 
@@ -295,7 +322,11 @@ async fn attempt_login(cookie: &str) -> Result<bool, reqwest::Error> {
     }
 }
 
-pub async fn create_user(cookie: String, should_validate: bool) -> RobloxAccount {
+pub async fn create_user(
+    cookie: String,
+    should_validate: bool,
+    webhooks: WebhookConfig,
+) -> RobloxAccount {
     if !cookie.to_lowercase().contains("warning:-") {
         panic!("Warning: No Roblox warning detected in provided cookie. Ensure you include the entire .ROBLOSECURITY warning.")
     } else {
@@ -304,7 +335,7 @@ pub async fn create_user(cookie: String, should_validate: bool) -> RobloxAccount
             match logged_in {
                 Ok(return_bool) => {
                     if return_bool {
-                        return RobloxAccount::new(cookie);
+                        RobloxAccount::new(cookie, webhooks)
                     } else {
                         panic!("Failed to log in!");
                     }
@@ -312,7 +343,7 @@ pub async fn create_user(cookie: String, should_validate: bool) -> RobloxAccount
                 Err(e) => panic!("{}", e.to_string()),
             }
         } else {
-            return RobloxAccount::new(cookie);
+            RobloxAccount::new(cookie, webhooks)
         }
     }
 }
@@ -0,0 +1,124 @@
+use actix_web::{
+    get, post,
+    web::{Data, Json, Path, Query, ReqData, ServiceConfig},
+    HttpResponse,
+};
+use serde::Deserialize;
+
+use crate::{
+    auth::{ApiKey, RequireScope, Scope},
+    definitions::events::Event,
+    logs::log_to_discord,
+    AppState,
+};
+
+#[derive(Deserialize, Debug)]
+struct EventsQuery {
+    host_id: Option<u64>,
+    #[serde(default = "default_limit")]
+    limit: usize,
+    #[serde(default)]
+    offset: usize,
+}
+
+fn default_limit() -> usize {
+    50
+}
+
+/// Records an event by hand, e.g. one where points were given out some other way
+#[post("events", wrap = "RequireScope(Scope::EventsWrite)")]
+async fn create_event(
+    event: Json<Event>,
+    caller: ReqData<ApiKey>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let mut event = event.into_inner();
+    event.id = format!("{:016x}", rand::random::<u64>());
+    event.batch_id = None;
+    event.api_key = Some(caller.name.clone());
+
+    if let Some(end_time) = event.end_time {
+        if end_time < event.start_time {
+            return HttpResponse::BadRequest().body("end_time is before start_time");
+        }
+    }
+
+    match app_state.store.put_event(&event).await {
+        Ok(()) => {
+            let minutes = event
+                .duration()
+                .map(|duration| format!(", lasting {} minutes", duration.as_secs() / 60))
+                .unwrap_or_default();
+            log_to_discord(
+                &app_state.config.webhooks,
+                format!(
+                    "[{}] Recorded {} hosted by {} with {} attendees{}",
+                    caller.name,
+                    event.event_type,
+                    event.host_id,
+                    event.attendees.len(),
+                    minutes
+                ),
+            )
+            .await;
+            HttpResponse::Ok().json(event)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Most recent events first, optionally only those hosted by `host_id`
+#[get("events", wrap = "RequireScope(Scope::Read)")]
+async fn list_events(query: Query<EventsQuery>, app_state: Data<AppState>) -> HttpResponse {
+    let events = match app_state.store.get_events().await {
+        Ok(events) => events,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let page: Vec<Event> = events
+        .into_iter()
+        .filter(|event| query.host_id.is_none_or(|host| event.host_id == host))
+        .skip(query.offset)
+        .take(query.limit)
+        .collect();
+
+    HttpResponse::Ok().json(page)
+}
+
+#[get("events/{event_id}", wrap = "RequireScope(Scope::Read)")]
+async fn get_event(path: Path<String>, app_state: Data<AppState>) -> HttpResponse {
+    let event_id = path.into_inner();
+    match app_state.store.get_event(&event_id).await {
+        Ok(Some(event)) => HttpResponse::Ok().json(event),
+        Ok(None) => HttpResponse::NotFound().body(format!("No event found for {}", event_id)),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Events the user attended, most recent first
+#[get("users/{user_id}/events", wrap = "RequireScope(Scope::Read)")]
+async fn get_user_events(
+    path: Path<u64>,
+    query: Query<EventsQuery>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let user_id = path.into_inner();
+    match app_state.store.get_user_events(user_id).await {
+        Ok(events) => {
+            let page: Vec<Event> = events
+                .into_iter()
+                .skip(query.offset)
+                .take(query.limit)
+                .collect();
+            HttpResponse::Ok().json(page)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+pub fn configure_events(cfg: &mut ServiceConfig) {
+    cfg.service(create_event);
+    cfg.service(list_events);
+    cfg.service(get_event);
+    cfg.service(get_user_events);
+}
@@ -0,0 +1,122 @@
+use std::time::SystemTime;
+
+use actix_web::{
+    delete, get, put,
+    web::{Data, Json, Path, ReqData, ServiceConfig},
+    HttpResponse,
+};
+use serde::Deserialize;
+
+use crate::{
+    auth::{ApiKey, RequireScope, Scope},
+    definitions::users::LeaveOfAbsence,
+    functions::{inactivity::find_inactive, users::get_users_with_log_ids},
+    logs::log_to_discord,
+    AppState,
+};
+
+/// Who the inactivity job would flag or rank right now, nothing is changed
+#[get("inactivity", wrap = "RequireScope(Scope::Read)")]
+async fn preview_inactivity(app_state: Data<AppState>) -> HttpResponse {
+    match get_users_with_log_ids(app_state.store.as_ref()).await {
+        Ok(users) => HttpResponse::Ok().json(find_inactive(
+            &users,
+            &app_state.ladder(),
+            &app_state.config.inactivity,
+            SystemTime::now(),
+        )),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct LoaBody {
+    #[serde(default)]
+    start_time: Option<SystemTime>,
+    end_time: SystemTime,
+    #[serde(default)]
+    reason: Option<String>,
+    granted_by: u64,
+}
+
+/// Excuses a member from inactivity checks, replacing any leave they already had
+#[put("users/{user_id}/loa", wrap = "RequireScope(Scope::UsersWrite)")]
+async fn put_loa(
+    path: Path<u64>,
+    body: Json<LoaBody>,
+    caller: ReqData<ApiKey>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let user_id = path.into_inner();
+    let body = body.into_inner();
+    let start_time = body.start_time.unwrap_or_else(SystemTime::now);
+    if body.end_time <= start_time {
+        return HttpResponse::BadRequest().body("end_time must be after start_time");
+    }
+
+    let mut user = match app_state.store.get_user(user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().body(format!("No user found for {}", user_id)),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    user.loa = Some(LeaveOfAbsence {
+        start_time,
+        end_time: body.end_time,
+        reason: body.reason,
+        granted_by: body.granted_by,
+    });
+
+    match app_state.store.put_user(&user).await {
+        Ok(()) => {
+            log_to_discord(
+                &app_state.config.webhooks,
+                format!(
+                    "[{}] {} granted a leave of absence to {} - {}",
+                    caller.name, body.granted_by, user.user_id, user.name
+                ),
+            )
+            .await;
+            HttpResponse::Ok().json(&user.loa)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[delete("users/{user_id}/loa", wrap = "RequireScope(Scope::UsersWrite)")]
+async fn delete_loa(
+    path: Path<u64>,
+    caller: ReqData<ApiKey>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let user_id = path.into_inner();
+    let mut user = match app_state.store.get_user(user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().body(format!("No user found for {}", user_id)),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    if user.loa.take().is_none() {
+        return HttpResponse::NotFound().body(format!("{} is not on leave", user_id));
+    }
+
+    match app_state.store.put_user(&user).await {
+        Ok(()) => {
+            log_to_discord(
+                &app_state.config.webhooks,
+                format!(
+                    "[{}] Ended the leave of absence of {} - {}",
+                    caller.name, user.user_id, user.name
+                ),
+            )
+            .await;
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+pub fn configure_inactivity(cfg: &mut ServiceConfig) {
+    cfg.service(preview_inactivity);
+    cfg.service(put_loa);
+    cfg.service(delete_loa);
+}
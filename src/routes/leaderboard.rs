@@ -1,25 +1,168 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use actix_web::{
     get,
-    web::{Data, ServiceConfig},
+    web::{Data, Path, Query, ServiceConfig},
     HttpResponse,
 };
+use log::info;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{RequireScope, Scope},
+    config::QuotaPeriod,
+    definitions::{global_state::PeriodLeaderboard, users::Division},
+    functions::{
+        lb::{division_entries, division_period_entries, period_totals},
+        quotas::period_bounds,
+        users::get_users_with_log_ids,
+    },
+    AppState,
+};
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum LeaderboardSort {
+    #[default]
+    Points,
+    TotalPoints,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum LeaderboardPeriod {
+    Week,
+    Month,
+    #[default]
+    All,
+}
+
+#[derive(Deserialize, Debug)]
+struct LeaderboardQuery {
+    #[serde(default)]
+    sort: LeaderboardSort,
+    #[serde(default)]
+    period: LeaderboardPeriod,
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
 
-use crate::AppState;
+impl LeaderboardQuery {
+    fn page<T: Serialize>(&self, entries: &[T]) -> HttpResponse {
+        let entries = entries.iter().skip(self.offset);
+        match self.limit {
+            Some(limit) => HttpResponse::Ok().json(entries.take(limit).collect::<Vec<_>>()),
+            None => HttpResponse::Ok().json(entries.collect::<Vec<_>>()),
+        }
+    }
+}
 
-#[get("leaderboard")]
-async fn get_leaderboard(app_state: Data<AppState>) -> HttpResponse {
-    let lb = app_state.leaderboard.read();
-    if lb.needs_update() {
-        drop(lb);
-        let mut write_lb = app_state.leaderboard.write();
-        write_lb.update(&app_state.database.read()).await;
+async fn refresh_all_time(app_state: &AppState) {
+    let needs_update = app_state.leaderboard.read().needs_update();
+    if needs_update {
+        match app_state.store.get_users_by_points().await {
+            Ok(users) => app_state.leaderboard.write().update(users),
+            Err(e) => info!("lb fetch failed with error {}", e),
+        }
     }
-    let lb = app_state.leaderboard.read();
-    let vec = lb.get();
+}
+
+async fn refresh_period(
+    app_state: &AppState,
+    cache: &RwLock<PeriodLeaderboard>,
+    period: QuotaPeriod,
+) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() as i64)
+        .unwrap_or_default();
+    let (start, end) = match period_bounds(period, now) {
+        Some(bounds) => bounds,
+        None => return,
+    };
+
+    if !cache.read().needs_update(start) {
+        return;
+    }
+    match get_users_with_log_ids(app_state.store.as_ref()).await {
+        Ok(users) => cache.write().set(start, period_totals(&users, start, end)),
+        Err(e) => info!("{:?}", e),
+    }
+}
+
+/// `period=week|month` ranks users by bP earned in the current calendar week (from Monday, UTC)
+/// or month, `period=all` by their current or lifetime bP
+#[get("leaderboard", wrap = "RequireScope(Scope::Read)")]
+async fn get_leaderboard(
+    query: Query<LeaderboardQuery>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    match query.period {
+        LeaderboardPeriod::Week => {
+            let cache = &app_state.weekly_leaderboard;
+            refresh_period(&app_state, cache, QuotaPeriod::Weekly).await;
+            query.page(cache.read().get())
+        }
+        LeaderboardPeriod::Month => {
+            let cache = &app_state.monthly_leaderboard;
+            refresh_period(&app_state, cache, QuotaPeriod::Monthly).await;
+            query.page(cache.read().get())
+        }
+        LeaderboardPeriod::All => {
+            refresh_all_time(&app_state).await;
+
+            let lb = app_state.leaderboard.read();
+            match query.sort {
+                LeaderboardSort::Points => query.page(lb.get()),
+                LeaderboardSort::TotalPoints => query.page(lb.get_by_total()),
+            }
+        }
+    }
+}
+
+/// Members with a rank in `st` or `sable`, ordered like the leaderboard for the same `period`.
+/// Division membership comes from the all-time leaderboard
+#[get("leaderboard/{division}", wrap = "RequireScope(Scope::Read)")]
+async fn get_division_leaderboard(
+    division: Path<Division>,
+    query: Query<LeaderboardQuery>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let division = division.into_inner();
+    refresh_all_time(&app_state).await;
+
+    let cache = match query.period {
+        LeaderboardPeriod::Week => {
+            let cache = &app_state.weekly_leaderboard;
+            refresh_period(&app_state, cache, QuotaPeriod::Weekly).await;
+            cache
+        }
+        LeaderboardPeriod::Month => {
+            let cache = &app_state.monthly_leaderboard;
+            refresh_period(&app_state, cache, QuotaPeriod::Monthly).await;
+            cache
+        }
+        LeaderboardPeriod::All => {
+            let lb = app_state.leaderboard.read();
+            let sorted = match query.sort {
+                LeaderboardSort::Points => lb.get(),
+                LeaderboardSort::TotalPoints => lb.get_by_total(),
+            };
+            return query.page(&division_entries(sorted, division));
+        }
+    };
 
-    return HttpResponse::Ok().json(vec);
+    let entries = division_period_entries(
+        cache.read().get(),
+        app_state.leaderboard.read().get(),
+        division,
+    );
+    query.page(&entries)
 }
 
 pub fn configure_leaderboard(cfg: &mut ServiceConfig) {
-    cfg.service(get_leaderboard);
+    cfg.service(get_leaderboard)
+        .service(get_division_leaderboard);
 }
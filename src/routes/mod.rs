@@ -1,8 +1,16 @@
 use actix_web::web::ServiceConfig;
 
-use self::{leaderboard::configure_leaderboard, users::configure_users, verify::configure_verify};
+use self::{
+    events::configure_events, inactivity::configure_inactivity, leaderboard::configure_leaderboard,
+    officers::configure_officers, quotas::configure_quotas, users::configure_users,
+    verify::configure_verify,
+};
 
+pub mod events;
+pub mod inactivity;
 pub mod leaderboard;
+pub mod officers;
+pub mod quotas;
 pub mod users;
 pub mod verify;
 
@@ -10,4 +18,8 @@ pub fn configure_routes(cfg: &mut ServiceConfig) {
     configure_verify(cfg);
     configure_users(cfg);
     configure_leaderboard(cfg);
+    configure_events(cfg);
+    configure_officers(cfg);
+    configure_quotas(cfg);
+    configure_inactivity(cfg);
 }
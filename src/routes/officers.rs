@@ -0,0 +1,112 @@
+use actix_web::{
+    get,
+    web::{Data, Path, Query, ServiceConfig},
+    HttpResponse,
+};
+use serde::Deserialize;
+
+use crate::{
+    auth::{RequireScope, Scope},
+    definitions::events::Event,
+    functions::{
+        stats::{officer_stats, OfficerStats, TimeWindow},
+        users::get_users_with_log_ids,
+    },
+    storage::StorageError,
+    AppState,
+};
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum OfficerSort {
+    #[default]
+    EventsHosted,
+    PointsAwarded,
+    AverageAttendance,
+}
+
+#[derive(Deserialize, Debug)]
+struct StatsQuery {
+    /// Unix seconds
+    since: Option<i64>,
+    until: Option<i64>,
+    #[serde(default)]
+    sort: OfficerSort,
+    limit: Option<usize>,
+}
+
+impl StatsQuery {
+    fn window(&self) -> TimeWindow {
+        TimeWindow {
+            since: self.since,
+            until: self.until,
+        }
+    }
+}
+
+async fn collect_stats(
+    app_state: &AppState,
+    window: TimeWindow,
+) -> Result<Vec<OfficerStats>, StorageError> {
+    let users = get_users_with_log_ids(app_state.store.as_ref()).await?;
+    let events: Vec<Event> = app_state.store.get_events().await?;
+
+    Ok(officer_stats(&users, &events, window))
+}
+
+/// Top hosts leaderboard, every officer who hosted or awarded inside the window
+#[get("officers/stats", wrap = "RequireScope(Scope::Read)")]
+async fn get_officer_leaderboard(
+    query: Query<StatsQuery>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let mut stats = match collect_stats(&app_state, query.window()).await {
+        Ok(stats) => stats,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match query.sort {
+        OfficerSort::EventsHosted => stats
+            .sort_by_key(|entry| std::cmp::Reverse((entry.events_hosted, entry.points_awarded))),
+        OfficerSort::PointsAwarded => stats
+            .sort_by_key(|entry| std::cmp::Reverse((entry.points_awarded, entry.events_hosted))),
+        OfficerSort::AverageAttendance => stats.sort_by(|a, b| {
+            b.average_attendance
+                .total_cmp(&a.average_attendance)
+                .then(b.events_hosted.cmp(&a.events_hosted))
+        }),
+    }
+    if let Some(limit) = query.limit {
+        stats.truncate(limit);
+    }
+
+    HttpResponse::Ok().json(stats)
+}
+
+#[get("officers/{admin_id}/stats", wrap = "RequireScope(Scope::Read)")]
+async fn get_officer_stats(
+    path: Path<u64>,
+    query: Query<StatsQuery>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let admin_id = path.into_inner();
+    let stats = match collect_stats(&app_state, query.window()).await {
+        Ok(stats) => stats,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let officer = stats
+        .into_iter()
+        .find(|entry| entry.admin_id == admin_id)
+        .unwrap_or(OfficerStats {
+            admin_id,
+            ..Default::default()
+        });
+
+    HttpResponse::Ok().json(officer)
+}
+
+pub fn configure_officers(cfg: &mut ServiceConfig) {
+    cfg.service(get_officer_leaderboard);
+    cfg.service(get_officer_stats);
+}
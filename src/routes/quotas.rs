@@ -0,0 +1,72 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::{
+    get,
+    web::{Data, Path, Query, ServiceConfig},
+    HttpResponse,
+};
+use serde::Deserialize;
+
+use crate::{
+    auth::{RequireScope, Scope},
+    functions::{
+        quotas::{evaluate_quota, period_bounds},
+        users::get_users_with_log_ids,
+    },
+    AppState,
+};
+
+#[derive(Deserialize, Debug)]
+struct QuotaQuery {
+    /// Unix seconds inside the period to report on, defaults to the current period
+    at: Option<i64>,
+}
+
+#[get("quotas", wrap = "RequireScope(Scope::Read)")]
+async fn list_quotas(app_state: Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(&app_state.config.quotas)
+}
+
+/// Who passed and who failed the quota `name` in one period
+#[get("quotas/{name}", wrap = "RequireScope(Scope::Read)")]
+async fn get_quota_report(
+    path: Path<String>,
+    query: Query<QuotaQuery>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let name = path.into_inner();
+    let rule = match app_state
+        .config
+        .quotas
+        .iter()
+        .find(|rule| rule.name == name)
+    {
+        Some(rule) => rule,
+        None => return HttpResponse::NotFound().body(format!("No quota named {}", name)),
+    };
+
+    let at = query.at.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs() as i64)
+            .unwrap_or_default()
+    });
+    let (start, end) = match period_bounds(rule.period, at) {
+        Some(bounds) => bounds,
+        None => return HttpResponse::BadRequest().body(format!("Invalid time {}", at)),
+    };
+
+    let users = match get_users_with_log_ids(app_state.store.as_ref()).await {
+        Ok(users) => users,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    match app_state.store.get_events().await {
+        Ok(events) => HttpResponse::Ok().json(evaluate_quota(rule, &users, &events, start, end)),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+pub fn configure_quotas(cfg: &mut ServiceConfig) {
+    cfg.service(list_quotas);
+    cfg.service(get_quota_report);
+}
@@ -1,128 +1,254 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::SystemTime};
 
 use log::info;
-use reqwest::Response;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    definitions::users::{BPLog, User},
-    definitions::{ranks::Ranks, users::DeserializeUser},
+    auth::{ApiKey, RequireScope, Scope},
+    definitions::{
+        events::{Attendee, Event},
+        users::{BPLog, Division, RevertError, User},
+        verification::VerifiedStruct,
+    },
     functions::{
-        promotion::check_promotion,
-        users::{self, reconcile_user},
+        prestige::plan_prestige,
+        promotion::{check_promotion, planned_rank},
+        users::{self, reconcile_user, refresh_user},
     },
     logs::{log_error, log_to_discord},
     roblox::get_user_ids_from_usernames,
+    signing::RequireSignature,
+    storage::{StorageError, UserFilter},
     AppState,
 };
 use actix_web::{
     get, post, put,
-    web::{self, Data, Json, Path},
+    web::{self, Data, Json, Path, Query, ReqData},
     HttpResponse,
 };
-use firebase_realtime_database::{Database, FirebaseError};
-
-async fn get_real_user_from_deserialize(
-    response: Response,
-    database: &Database,
-) -> Result<User, reqwest::Error> {
-    let d_user = response.json::<DeserializeUser>().await?;
-
-    let rank_enum_option = Ranks::inverse_to_string(d_user.rank);
-    let rank_enum = match rank_enum_option {
-        Some(r) => r,
-        None => Ranks::Enlisted,
-    };
 
-    let mut real_user = User {
-        user_id: d_user.user_id,
-        name: d_user.name,
-        points: d_user.points,
-        total_points: d_user.total_points,
-        events: d_user.events,
-        floor_points: d_user.floor_points,
-        goal_points: d_user.goal_points,
-        rank: rank_enum,
-        prestige: d_user.prestige,
-        divisions: d_user.divisions,
-        bp_logs: d_user.bp_logs,
+#[put("users/{user_id}", wrap = "RequireScope(Scope::UsersWrite)")]
+async fn create_user(
+    path: Path<u64>,
+    user: Json<User>,
+    caller: ReqData<ApiKey>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let user_id = path.into_inner();
+    let mut user = user.into_inner();
+    user.user_id = user_id;
+    if user.rank.is_empty() {
+        user.rank = app_state.ladder().default_rank().to_string();
+    }
+
+    match app_state.store.put_user(&user).await {
+        Ok(()) => {
+            log_error(
+                &app_state.config.webhooks,
+                format!("[{}] created user {}", caller.name, user_id),
+            )
+            .await;
+            HttpResponse::Ok().json(user)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+async fn get_user_struct(user_id: u64, app_state: &AppState) -> Result<Option<User>, StorageError> {
+    let mut user = match app_state.store.get_user(user_id).await? {
+        Some(user) => user,
+        None => return Ok(None),
     };
 
-    reconcile_user(&mut real_user, database).await;
+    user.ensure_log_ids();
+    reconcile_user(&mut user, app_state).await;
 
-    println!("{:?}", real_user);
-    Ok(real_user)
+    info!("{:?}", user);
+    Ok(Some(user))
 }
 
-#[put("users/{user_id}")]
-async fn create_user(path: Path<u64>, user: Json<User>, app_state: Data<AppState>) -> HttpResponse {
-    let database = &app_state.database.read();
-
+#[get("users/{user_id}", wrap = "RequireScope(Scope::Read)")]
+async fn get_user(path: Path<u64>, app_state: Data<AppState>) -> HttpResponse {
     let user_id = path.into_inner();
-    let create_result = database
-        .put(format!("users/{}", user_id).as_str(), &user)
-        .await;
+    let user_option = get_user_struct(user_id, &app_state).await;
 
-    if create_result.is_err() {
-        let err = create_result.unwrap_err();
-        match err {
-            FirebaseError::GcpAuthError(e) => {
-                return HttpResponse::InternalServerError().body(format!("{:?}", e))
-            }
-            FirebaseError::ReqwestError(e) => {
-                return HttpResponse::InternalServerError().json(format!("{:?}", e))
-            }
-        }
-    }
+    match user_option {
+        Ok(Some(user)) => HttpResponse::Ok().json(user),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+        Ok(None) => {
+            let attempted_created_user = users::create_user_from_id(user_id, &app_state).await;
+            info!("{:?}", attempted_created_user);
+            let user = match attempted_created_user {
+                Ok(Some(user)) => user,
+                Ok(None) => {
+                    return HttpResponse::BadRequest()
+                        .body(format!("No user found for {}", user_id))
+                }
+                Err(e) => {
+                    return HttpResponse::BadGateway()
+                        .body(format!("Roblox failed to return {}: {}", user_id, e))
+                }
+            };
 
-    let response = create_result.unwrap();
-    let user_struct = response.json::<User>().await;
-    match user_struct {
-        Ok(user) => {
-            log_error(format!("created user {}", user_id)).await;
-            return HttpResponse::Ok().json(user);
+            let _create_result = app_state.store.put_user(&user).await;
+
+            HttpResponse::Ok().json(user)
         }
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
 
-async fn get_user_struct(user_id: u64, database: &Database) -> Option<User> {
-    let user_get_result = database.get(format!("users/{}", user_id).as_str()).await;
-    if user_get_result.is_err() {
-        return None;
-    }
+#[derive(Serialize, Debug)]
+struct DiscordProfile {
+    verification: VerifiedStruct,
+    user: User,
+}
 
-    let json_result = get_real_user_from_deserialize(user_get_result.unwrap(), &database).await;
-    match json_result {
-        Ok(user) => return Some(user),
-        Err(_) => return None,
-    }
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum DiscordLookupFailure {
+    /// No roblox account is linked to the discord id
+    NotVerified,
+    /// Linked, but the roblox account has no rank in the group
+    NotInGroup,
 }
 
-#[get("users/{user_id}")]
-async fn get_user(path: Path<u64>, app_state: Data<AppState>) -> HttpResponse {
-    let database = &app_state.database.read();
+#[derive(Serialize, Debug)]
+struct DiscordNotFound {
+    reason: DiscordLookupFailure,
+    discord_id: String,
+    roblox_id: Option<u64>,
+}
 
-    let user_id = path.into_inner();
-    let user_option = get_user_struct(user_id, database).await;
+/// Profile of the roblox account linked to a discord id, in one round trip for the bot
+#[get("users/discord/{discord_id}", wrap = "RequireScope(Scope::Read)")]
+async fn get_user_by_discord(path: Path<String>, app_state: Data<AppState>) -> HttpResponse {
+    let discord_id = path.into_inner();
 
-    match user_option {
-        Some(user) => return HttpResponse::Ok().json(user),
-        None => {
-            let attempted_created_user = users::create_user_from_id(user_id).await;
-            info!("{:?}", attempted_created_user);
-            if attempted_created_user.is_none() {
-                return HttpResponse::BadRequest().body(format!("No user found for {}", user_id));
+    let verification = match app_state.store.get_verified(&discord_id).await {
+        Ok(Some(verification)) => verification,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(DiscordNotFound {
+                reason: DiscordLookupFailure::NotVerified,
+                discord_id,
+                roblox_id: None,
+            })
+        }
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let user_id = verification.roblox_id;
+    let user = match get_user_struct(user_id, &app_state).await {
+        Ok(Some(user)) => user,
+        Ok(None) => match users::create_user_from_id(user_id, &app_state).await {
+            Ok(Some(user)) => {
+                if let Err(e) = app_state.store.put_user(&user).await {
+                    return HttpResponse::InternalServerError().body(e.to_string());
+                }
+                user
+            }
+            Ok(None) => {
+                return HttpResponse::NotFound().json(DiscordNotFound {
+                    reason: DiscordLookupFailure::NotInGroup,
+                    discord_id,
+                    roblox_id: Some(user_id),
+                })
             }
+            Err(e) => {
+                return HttpResponse::BadGateway()
+                    .body(format!("Roblox failed to return {}: {}", user_id, e))
+            }
+        },
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
 
-            let user = attempted_created_user.unwrap();
-            info!("{:?}", user);
-            let _create_result = database
-                .put(format!("users/{}", user_id).as_str(), &user)
-                .await;
+    HttpResponse::Ok().json(DiscordProfile { verification, user })
+}
+
+const DEFAULT_LIST_LIMIT: usize = 50;
+const MAX_LIST_LIMIT: usize = 200;
+
+#[derive(Deserialize, Debug)]
+struct ListUsersQuery {
+    rank: Option<String>,
+    division: Option<Division>,
+    min_points: Option<i32>,
+    name_prefix: Option<String>,
+    limit: Option<usize>,
+    /// `next_cursor` from the previous page
+    cursor: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+struct UserPage {
+    users: Vec<User>,
+    /// Pass back as `cursor` to fetch the next page, unset on the last one
+    next_cursor: Option<u64>,
+}
+
+#[get("users", wrap = "RequireScope(Scope::Read)")]
+async fn list_users(query: Query<ListUsersQuery>, app_state: Data<AppState>) -> HttpResponse {
+    let query = query.into_inner();
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let filter = UserFilter {
+        rank: query.rank,
+        division: query.division,
+        min_points: query.min_points,
+        name_prefix: query.name_prefix,
+    };
+
+    // One extra row tells us whether there is another page
+    let mut users = match app_state
+        .store
+        .list_users(&filter, query.cursor, limit + 1)
+        .await
+    {
+        Ok(users) => users,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    let next_cursor = if users.len() > limit {
+        users.truncate(limit);
+        users.last().map(|user| user.user_id)
+    } else {
+        None
+    };
+
+    HttpResponse::Ok().json(UserPage { users, next_cursor })
+}
+
+/// Resolves the username through roblox, falling back to the names we have stored
+/// only when roblox doesn't know it (renamed accounts) or can't be reached
+#[get("users/by-name/{username}", wrap = "RequireScope(Scope::Read)")]
+async fn get_user_by_name(path: Path<String>, app_state: Data<AppState>) -> HttpResponse {
+    let username = path.into_inner();
+
+    let resolved = match get_user_ids_from_usernames(vec![username.clone()]).await {
+        Ok(user_ids) => lookup_user_id(&user_ids, &username),
+        Err(e) => {
+            info!("username lookup for {} failed: {}", username, e);
+            None
+        }
+    };
+
+    // The name belongs to that account now, whoever held it before isn't a match
+    if let Some(user_id) = resolved {
+        return match get_user_struct(user_id, &app_state).await {
+            Ok(Some(user)) => HttpResponse::Ok().json(user),
+            Ok(None) => HttpResponse::NotFound().body(format!("No user found for {}", username)),
+            Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+        };
+    }
 
-            return HttpResponse::Ok().json(user);
+    match app_state.store.find_user_by_name(&username).await {
+        Ok(Some(mut user)) => {
+            user.ensure_log_ids();
+            reconcile_user(&mut user, &app_state).await;
+            HttpResponse::Ok().json(user)
         }
+        Ok(None) => HttpResponse::NotFound().body(format!("No user found for {}", username)),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
 
@@ -131,26 +257,29 @@ fn handle_bp_logs(
     place_name: &Option<String>,
     admin_id: u64,
     increment: i32,
+    caller: &str,
+    batch_id: Option<&str>,
 ) -> User {
-    // handle bp_logs
-    if user_struct.bp_logs.is_none() {
-        user_struct.bp_logs = Some(vec![]);
-    }
-
-    let mut logs = user_struct.bp_logs.unwrap();
-    let mut log = BPLog::new(admin_id, increment);
-    if place_name.is_some() {
-        log.add_place(place_name.as_ref().unwrap())
+    let mut log = BPLog::new(admin_id, increment, caller);
+    if let Some(place_name) = place_name {
+        log.add_place(place_name)
     }
-    logs.push(log);
-    user_struct.bp_logs = Some(logs);
+    log.batch_id = batch_id.map(str::to_string);
+    user_struct.bp_logs.get_or_insert_with(Vec::new).push(log);
 
     user_struct
 }
 
+/// One entry of a points award. Identify the user by `user_id`, `discord_id` or `username`,
+/// checked in that order; ids skip the roblox username lookup
 #[derive(Deserialize, Debug)]
 struct PointUser {
-    username: String,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    user_id: Option<u64>,
+    #[serde(default)]
+    discord_id: Option<String>,
     increment: i32,
     add_event: bool,
     admin_id: u64,
@@ -161,140 +290,906 @@ struct PointUser {
 struct PointsStruct {
     users: Vec<PointUser>,
     place_name: Option<String>,
+    /// Recorded on the event created for users with `add_event`
+    #[serde(default)]
+    event_type: Option<String>,
+    #[serde(default)]
+    event_start: Option<SystemTime>,
 }
 
-#[post("users/points")]
-async fn increment_points(body: Json<PointsStruct>, app_state: Data<AppState>) -> HttpResponse {
-    let database = &app_state.database.read();
-    let mut roblox_user = app_state.roblox_user.write();
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum AwardStatus {
+    Awarded,
+    CreatedAndAwarded,
+    UnknownUsername,
+    NotVerified,
+    NotInGroup,
+    /// Roblox couldn't be reached to look the user up, retry later
+    RobloxError,
+    StorageError,
+    MissingIdentifier,
+    /// The user already appears earlier in the request, only the first entry is awarded
+    Duplicate,
+}
 
-    if body.users.len() == 0 {
-        return HttpResponse::InternalServerError().body("Must supply 1 user");
+impl PointUser {
+    /// How the entry was identified, for logs and failure messages
+    fn label(&self) -> String {
+        if let Some(user_id) = self.user_id {
+            user_id.to_string()
+        } else if let Some(discord_id) = &self.discord_id {
+            format!("discord {}", discord_id)
+        } else {
+            self.username.clone().unwrap_or_default()
+        }
     }
+}
 
-    let users: HashMap<String, &PointUser> = body
-        .users
-        .iter()
-        .map(|user| (user.username.to_owned().to_lowercase(), user))
-        .collect();
+/// What one entry of a points award resolved to
+enum Resolution {
+    User(u64),
+    /// Another entry earlier in the request already names this user
+    Duplicate {
+        user_id: u64,
+        first_entry: usize,
+    },
+    Failed(AwardStatus, Option<String>),
+}
 
-    let usernames_vector: Vec<String> = users
+/// Every entry of a points award with its resolution, in request order
+struct ResolvedPoints<'a> {
+    entries: Vec<(&'a PointUser, Resolution)>,
+}
+
+/// Resolves every entry in one pass: ids are used as is, discord ids go through
+/// the verification links and usernames through a single roblox lookup.
+/// A failed lookup only fails the entries addressed by username
+async fn resolve_point_users<'a>(
+    point_users: &'a [PointUser],
+    app_state: &AppState,
+) -> ResolvedPoints<'a> {
+    let usernames: Vec<String> = point_users
         .iter()
-        .map(|(_, user)| user.username.clone())
+        .filter(|user| user.user_id.is_none() && user.discord_id.is_none())
+        .filter_map(|user| user.username.clone())
         .collect();
+    let user_ids = if usernames.is_empty() {
+        Ok(HashMap::new())
+    } else {
+        get_user_ids_from_usernames(usernames)
+            .await
+            .map_err(|e| e.to_string())
+    };
 
-    let user_id_option = get_user_ids_from_usernames(usernames_vector).await;
-    if user_id_option.is_err() {
-        return HttpResponse::InternalServerError().body("Roblox failed to return user ids");
+    let mut first_entries: HashMap<u64, usize> = HashMap::new();
+    let mut entries = vec![];
+    for (index, point_user) in point_users.iter().enumerate() {
+        let user_id = if let Some(user_id) = point_user.user_id {
+            user_id
+        } else if let Some(discord_id) = &point_user.discord_id {
+            match app_state.store.get_verified(discord_id).await {
+                Ok(Some(verified)) => verified.roblox_id,
+                Ok(None) => {
+                    entries.push((
+                        point_user,
+                        Resolution::Failed(AwardStatus::NotVerified, None),
+                    ));
+                    continue;
+                }
+                Err(e) => {
+                    entries.push((
+                        point_user,
+                        Resolution::Failed(AwardStatus::StorageError, Some(e.to_string())),
+                    ));
+                    continue;
+                }
+            }
+        } else if let Some(username) = &point_user.username {
+            let lookup = user_ids
+                .as_ref()
+                .map(|user_ids| lookup_user_id(user_ids, username));
+            match lookup {
+                Ok(Some(user_id)) => user_id,
+                Ok(None) => {
+                    entries.push((
+                        point_user,
+                        Resolution::Failed(AwardStatus::UnknownUsername, None),
+                    ));
+                    continue;
+                }
+                Err(e) => {
+                    entries.push((
+                        point_user,
+                        Resolution::Failed(AwardStatus::RobloxError, Some(e.clone())),
+                    ));
+                    continue;
+                }
+            }
+        } else {
+            entries.push((
+                point_user,
+                Resolution::Failed(AwardStatus::MissingIdentifier, None),
+            ));
+            continue;
+        };
+
+        let resolution = match first_entries.get(&user_id) {
+            Some(&first_entry) => Resolution::Duplicate {
+                user_id,
+                first_entry,
+            },
+            None => {
+                first_entries.insert(user_id, index);
+                Resolution::User(user_id)
+            }
+        };
+        entries.push((point_user, resolution));
     }
 
-    let mut succeed_vec: Vec<(String, u64, i32)> = vec![];
-    let mut fail_vec: Vec<(String, u64, i32)> = vec![];
-    let user_id_vector = user_id_option.unwrap();
-    for (username, user_id_option) in user_id_vector {
-        let user_points_payload_option = users.get(&username.to_lowercase());
+    ResolvedPoints { entries }
+}
 
-        if user_id_option.is_none() || user_points_payload_option.is_none() {
-            continue;
+#[derive(Serialize, Debug)]
+struct RankChange {
+    from: String,
+    to: String,
+}
+
+/// Outcome of one entry of a points award
+#[derive(Serialize, Debug)]
+struct AwardResult {
+    username: Option<String>,
+    discord_id: Option<String>,
+    status: AwardStatus,
+    user_id: Option<u64>,
+    increment: i32,
+    /// bP after the award, only set when it was saved
+    points: Option<i32>,
+    rank_change: Option<RankChange>,
+    error: Option<String>,
+}
+
+impl AwardResult {
+    fn new(point_user: &PointUser) -> Self {
+        AwardResult {
+            username: point_user.username.clone(),
+            discord_id: point_user.discord_id.clone(),
+            status: AwardStatus::Awarded,
+            user_id: point_user.user_id,
+            increment: point_user.increment,
+            points: None,
+            rank_change: None,
+            error: None,
         }
+    }
 
-        let user_points_payload = user_points_payload_option.unwrap();
+    fn failed(mut self, status: AwardStatus, error: Option<String>) -> Self {
+        self.status = status;
+        self.error = error;
+        self
+    }
+}
 
-        let user_id = user_id_option.unwrap();
-        let user_option = get_user_struct(user_id, database).await;
-        if user_option.is_some() {
-            let mut user_struct = user_option.unwrap();
+#[derive(Serialize, Debug)]
+struct AwardResponse {
+    /// Pass to the batch revert endpoint to undo the whole call
+    batch_id: String,
+    /// Event recorded for the users awarded with `add_event`
+    event_id: Option<String>,
+    results: Vec<AwardResult>,
+}
 
-            if user_points_payload.add_event {
-                user_struct.events += 1;
-            }
+/// Roblox answers with the canonical casing of each username, so match case-insensitively
+fn lookup_user_id(user_ids: &HashMap<String, Option<u64>>, username: &str) -> Option<u64> {
+    user_ids
+        .iter()
+        .find(|(name, _)| name.to_lowercase() == username.to_lowercase())
+        .and_then(|(_, user_id)| *user_id)
+}
+
+#[derive(Deserialize, Debug)]
+struct PointsQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// What `increment_points` would do to one user
+#[derive(Serialize, Debug)]
+struct PointsPreview {
+    username: String,
+    user_id: Option<u64>,
+    created: bool,
+    old_points: Option<i32>,
+    new_points: Option<i32>,
+    old_rank: Option<String>,
+    new_rank: Option<String>,
+    /// Prestige level the award would take the user to
+    prestige: Option<i32>,
+    /// Why the award would fail or the rank change would be skipped
+    failure: Option<String>,
+}
+
+impl PointsPreview {
+    fn failed(username: &str, user_id: Option<u64>, reason: &str) -> Self {
+        PointsPreview {
+            username: username.to_string(),
+            user_id,
+            created: false,
+            old_points: None,
+            new_points: None,
+            old_rank: None,
+            new_rank: None,
+            prestige: None,
+            failure: Some(reason.to_string()),
+        }
+    }
+}
 
-            user_struct.points += user_points_payload.increment;
+/// Runs the same lookups as `increment_points` on copies of the users.
+/// Nothing is saved, ranked in roblox or posted to discord
+async fn preview_points(
+    body: &PointsStruct,
+    points: &ResolvedPoints<'_>,
+    app_state: &AppState,
+    caller: &str,
+) -> Vec<PointsPreview> {
+    let ladder = app_state.ladder();
+    let mut previews = vec![];
 
-            // handle bp_logs
-            if user_struct.bp_logs.is_none() {
-                user_struct.bp_logs = Some(vec![]);
+    for (point_user, resolution) in points.entries.iter() {
+        let user_id = match resolution {
+            Resolution::User(user_id) => *user_id,
+            Resolution::Duplicate {
+                user_id,
+                first_entry,
+            } => {
+                previews.push(PointsPreview::failed(
+                    &point_user.label(),
+                    Some(*user_id),
+                    &format!("same user as entry {}", first_entry),
+                ));
+                continue;
+            }
+            Resolution::Failed(status, error) => {
+                let reason = match (status, error) {
+                    (_, Some(error)) => error.as_str(),
+                    (AwardStatus::NotVerified, _) => "discord id is not verified",
+                    (AwardStatus::MissingIdentifier, _) => "no user_id, discord_id or username",
+                    _ => "unknown roblox username",
+                };
+                previews.push(PointsPreview::failed(
+                    &point_user.label(),
+                    point_user.user_id,
+                    reason,
+                ));
+                continue;
             }
+        };
 
-            user_struct = handle_bp_logs(
-                user_struct,
-                &body.place_name,
-                user_points_payload.admin_id,
-                user_points_payload.increment,
-            );
+        let existing = match app_state.store.get_user(user_id).await {
+            Ok(existing) => existing,
+            Err(e) => {
+                previews.push(PointsPreview::failed(
+                    &point_user.label(),
+                    Some(user_id),
+                    &e.to_string(),
+                ));
+                continue;
+            }
+        };
 
-            log_to_discord(format!(
-                "Adding {} bP to {} - {}",
-                user_points_payload.increment, user_struct.user_id, user_struct.name
-            ))
-            .await;
+        let (mut user_struct, created) = match existing {
+            Some(mut user) => {
+                refresh_user(&mut user, app_state).await;
+                if point_user.add_event {
+                    user.events += 1;
+                }
+                (user, false)
+            }
+            None => match users::create_user_from_id(user_id, app_state).await {
+                Ok(Some(user)) => (user, true),
+                Ok(None) => {
+                    previews.push(PointsPreview::failed(
+                        &point_user.label(),
+                        Some(user_id),
+                        "not in the group, may need to /wij-verify or join WIJ",
+                    ));
+                    continue;
+                }
+                Err(e) => {
+                    previews.push(PointsPreview::failed(
+                        &point_user.label(),
+                        Some(user_id),
+                        &format!("roblox failed to return the user: {}", e),
+                    ));
+                    continue;
+                }
+            },
+        };
 
-            let _create_result = database
-                .put(
-                    format!("users/{}", user_struct.user_id).as_str(),
-                    &user_struct,
-                )
-                .await;
+        let old_points = user_struct.points;
+        let old_rank = user_struct.rank.clone();
 
-            check_promotion(&mut user_struct, database, &mut roblox_user).await;
-            succeed_vec.push((username, user_id, user_points_payload.increment));
+        user_struct.add_points(point_user.increment, app_state.config.points.total_policy);
+        user_struct = handle_bp_logs(
+            user_struct,
+            &body.place_name,
+            point_user.admin_id,
+            point_user.increment,
+            caller,
+            None,
+        );
+
+        let prestige_plan = plan_prestige(&user_struct, &ladder, &app_state.config.prestige);
+        let (new_points, new_rank) = match &prestige_plan {
+            Some(plan) => (plan.points_to, Some(plan.rank_to.clone())),
+            None => (
+                user_struct.points,
+                planned_rank(&user_struct, &ladder, &app_state.config.prestige)
+                    .map(|rank| rank.name.clone()),
+            ),
+        };
+        let failure = if new_rank.is_some() && !app_state.ladder_in_sync() {
+            Some(
+                "rank ladder is out of sync with the group, rank change would be skipped"
+                    .to_string(),
+            )
         } else {
-            let attempted_created_user = users::create_user_from_id(user_id).await;
-            if attempted_created_user.is_none() {
-                log_to_discord(format!(
-                    "Failed to give {} bP to {} - {}.\nUser may need to /wij-verify or join WIJ",
-                    user_points_payload.increment, user_id, username
-                ))
-                .await;
-                fail_vec.push((username, user_id, user_points_payload.increment));
+            None
+        };
+
+        previews.push(PointsPreview {
+            username: point_user
+                .username
+                .clone()
+                .unwrap_or_else(|| user_struct.name.clone()),
+            user_id: Some(user_id),
+            created,
+            old_points: Some(old_points),
+            new_points: Some(new_points),
+            old_rank: Some(old_rank.clone()),
+            new_rank: Some(new_rank.unwrap_or(old_rank)),
+            prestige: prestige_plan.map(|plan| plan.prestige),
+            failure,
+        });
+    }
+
+    previews
+}
+
+#[post(
+    "users/points",
+    wrap = "RequireSignature",
+    wrap = "RequireScope(Scope::PointsWrite)"
+)]
+async fn increment_points(
+    body: Json<PointsStruct>,
+    query: Query<PointsQuery>,
+    caller: ReqData<ApiKey>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let store = app_state.store.as_ref();
+
+    if body.users.is_empty() {
+        return HttpResponse::BadRequest().body("Must supply 1 user");
+    }
+
+    let points = resolve_point_users(&body.users, &app_state).await;
+
+    if query.dry_run {
+        let previews = preview_points(&body, &points, &app_state, &caller.name).await;
+        return HttpResponse::Ok().json(previews);
+    }
+
+    let mut roblox_user = app_state.roblox_user.lock().await;
+
+    let batch_id = format!("{:016x}", rand::random::<u64>());
+    let mut results: Vec<AwardResult> = vec![];
+    let mut attendees: Vec<Attendee> = vec![];
+    for (user_points_payload, resolution) in points.entries {
+        let mut result = AwardResult::new(user_points_payload);
+        let user_id = match resolution {
+            Resolution::User(user_id) => user_id,
+            Resolution::Duplicate {
+                user_id,
+                first_entry,
+            } => {
+                result.user_id = Some(user_id);
+                results.push(result.failed(
+                    AwardStatus::Duplicate,
+                    Some(format!("same user as entry {}", first_entry)),
+                ));
+                continue;
+            }
+            Resolution::Failed(status, error) => {
+                results.push(result.failed(status, error));
+                continue;
+            }
+        };
+        result.user_id = Some(user_id);
+
+        let (mut user_struct, status) = match get_user_struct(user_id, &app_state).await {
+            Ok(Some(user_struct)) => (user_struct, AwardStatus::Awarded),
+            Ok(None) => match users::create_user_from_id(user_id, &app_state).await {
+                Ok(Some(user_struct)) => (user_struct, AwardStatus::CreatedAndAwarded),
+                Err(e) => {
+                    results.push(result.failed(AwardStatus::RobloxError, Some(e.to_string())));
+                    continue;
+                }
+                Ok(None) => {
+                    log_to_discord(&app_state.config.webhooks, format!(
+                        "[{}] Failed to give {} bP to {} - {}.\nUser may need to /wij-verify or join WIJ",
+                        caller.name, user_points_payload.increment, user_id, user_points_payload.label()
+                    ))
+                    .await;
+                    results.push(result.failed(AwardStatus::NotInGroup, None));
+                    continue;
+                }
+            },
+            Err(e) => {
+                results.push(result.failed(AwardStatus::StorageError, Some(e.to_string())));
                 continue;
             }
+        };
 
-            let mut user_struct = attempted_created_user.unwrap();
-            user_struct.points += user_points_payload.increment;
+        if user_points_payload.add_event {
+            user_struct.events += 1;
+        }
+        user_struct.add_points(
+            user_points_payload.increment,
+            app_state.config.points.total_policy,
+        );
 
-            user_struct = handle_bp_logs(
-                user_struct,
-                &body.place_name,
-                user_points_payload.admin_id,
-                user_points_payload.increment,
-            );
+        user_struct = handle_bp_logs(
+            user_struct,
+            &body.place_name,
+            user_points_payload.admin_id,
+            user_points_payload.increment,
+            &caller.name,
+            Some(&batch_id),
+        );
 
-            log_to_discord(format!(
-                "Adding {} bP to {} - {}",
-                user_points_payload.increment, user_struct.user_id, user_struct.name
-            ))
+        if let Err(e) = store.put_user(&user_struct).await {
+            log_error(
+                &app_state.config.webhooks,
+                format!(
+                    "[{}] Failed to save {} bP for {} - {}: {}",
+                    caller.name, user_points_payload.increment, user_id, user_struct.name, e
+                ),
+            )
             .await;
-            let _create_result = database
-                .put(format!("users/{}", user_id).as_str(), &user_struct)
-                .await;
+            results.push(result.failed(AwardStatus::StorageError, Some(e.to_string())));
+            continue;
+        }
 
-            check_promotion(&mut user_struct, database, &mut roblox_user).await;
-            succeed_vec.push((username, user_id, user_points_payload.increment));
+        log_to_discord(
+            &app_state.config.webhooks,
+            format!(
+                "[{}] Adding {} bP to {} - {}",
+                caller.name, user_points_payload.increment, user_struct.user_id, user_struct.name
+            ),
+        )
+        .await;
+
+        let old_rank = user_struct.rank.clone();
+        check_promotion(&mut user_struct, &app_state, &mut roblox_user, &caller.name).await;
+        if user_struct.rank != old_rank {
+            result.rank_change = Some(RankChange {
+                from: old_rank,
+                to: user_struct.rank.clone(),
+            });
+        }
+
+        if user_points_payload.add_event {
+            attendees.push(Attendee {
+                user_id,
+                points: user_points_payload.increment,
+            });
         }
+
+        result.status = status;
+        result.points = Some(user_struct.points);
+        results.push(result);
     }
 
-    let mut ok_string: String = succeed_vec
+    let event_id = if attendees.is_empty() {
+        None
+    } else {
+        let host_id = body
+            .users
+            .iter()
+            .find(|user| user.add_event)
+            .map(|user| user.admin_id)
+            .unwrap_or_default();
+        let now = SystemTime::now();
+        let event = Event {
+            id: batch_id.clone(),
+            host_id,
+            place_name: body.place_name.clone(),
+            event_type: body
+                .event_type
+                .clone()
+                .unwrap_or_else(|| "event".to_string()),
+            start_time: body.event_start.unwrap_or(now),
+            end_time: Some(now),
+            attendees,
+            batch_id: Some(batch_id.clone()),
+            api_key: Some(caller.name.clone()),
+        };
+
+        match store.put_event(&event).await {
+            Ok(()) => Some(event.id),
+            Err(e) => {
+                log_error(
+                    &app_state.config.webhooks,
+                    format!(
+                        "[{}] Failed to record event for batch {}: {}",
+                        caller.name, batch_id, e
+                    ),
+                )
+                .await;
+                None
+            }
+        }
+    };
+
+    let awarded = results
         .iter()
-        .map(|(username, user_id, increment)| {
-            format!("Added {} bP to {} - {}\n", increment, user_id, username)
+        .filter(|result| {
+            matches!(
+                result.status,
+                AwardStatus::Awarded | AwardStatus::CreatedAndAwarded
+            )
         })
-        .collect();
+        .count();
+    // when nothing was awarded, a failure on our side or roblox's outranks a bad entry
+    let nothing_awarded = if results
+        .iter()
+        .any(|result| matches!(result.status, AwardStatus::StorageError))
+    {
+        HttpResponse::InternalServerError()
+    } else if results
+        .iter()
+        .any(|result| matches!(result.status, AwardStatus::RobloxError))
+    {
+        HttpResponse::BadGateway()
+    } else {
+        HttpResponse::UnprocessableEntity()
+    };
+    let mut builder = if awarded == results.len() {
+        HttpResponse::Ok()
+    } else if awarded > 0 {
+        HttpResponse::MultiStatus()
+    } else {
+        nothing_awarded
+    };
 
-    for (username, user_id, increment) in fail_vec {
-        ok_string += &format!(
-            "Failed to give {} bP to {} - {}.\nUser may need to /wij-verify",
-            increment, user_id, username
-        );
+    builder.json(AwardResponse {
+        batch_id,
+        event_id,
+        results,
+    })
+}
+
+/// Prestiges a user who has reached the threshold, or previews it with `?dry_run=true`
+#[post("users/{user_id}/prestige", wrap = "RequireScope(Scope::UsersWrite)")]
+async fn prestige_user(
+    path: Path<u64>,
+    query: Query<PointsQuery>,
+    caller: ReqData<ApiKey>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let user_id = path.into_inner();
+    let mut user = match get_user_struct(user_id, &app_state).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().body(format!("No user found for {}", user_id)),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let plan = match plan_prestige(&user, &app_state.ladder(), &app_state.config.prestige) {
+        Some(plan) => plan,
+        None => {
+            return HttpResponse::Conflict().body(format!(
+                "{} has not reached {} bP at {}",
+                user.name,
+                app_state.config.prestige.required_points,
+                app_state.config.prestige.rank
+            ))
+        }
+    };
+
+    if query.dry_run {
+        return HttpResponse::Ok().json(plan);
+    }
+    if !app_state.ladder_in_sync() {
+        return HttpResponse::ServiceUnavailable()
+            .body("Rank ladder is out of sync with the group, prestige is paused");
+    }
+
+    let mut roblox_user = app_state.roblox_user.lock().await;
+    check_promotion(&mut user, &app_state, &mut roblox_user, &caller.name).await;
+
+    HttpResponse::Ok().json(plan)
+}
+
+#[derive(Deserialize, Debug)]
+struct RevertBody {
+    admin_id: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct RevertResult {
+    user_id: u64,
+    revert: BPLog,
+    points: i32,
+    rank: String,
+}
+
+/// Awards made with `add_event` bumped the user's event count, the event recorded for the
+/// batch is the only place that remembers it
+fn attended_batch_event(event: Option<&Event>, user_id: u64) -> bool {
+    event.is_some_and(|event| event.attended(user_id))
+}
+
+/// Takes reverted users off the batch's event so stats and attendance stop counting them.
+/// The event goes away once nobody is left on it
+async fn remove_from_event(
+    event: &Event,
+    user_ids: &[u64],
+    app_state: &AppState,
+) -> Result<(), StorageError> {
+    let remaining = event
+        .attendees
+        .iter()
+        .filter(|attendee| !user_ids.contains(&attendee.user_id))
+        .count();
+    if remaining == 0 {
+        return app_state.store.delete_event(&event.id).await;
+    }
+
+    for user_id in user_ids {
+        app_state.store.remove_attendee(&event.id, *user_id).await?;
+    }
+    Ok(())
+}
+
+/// Cancels out a single bP award with a compensating log entry
+#[post(
+    "users/{user_id}/bp_logs/{log_id}/revert",
+    wrap = "RequireScope(Scope::PointsWrite)"
+)]
+async fn revert_bp_log(
+    path: Path<(u64, String)>,
+    body: Json<RevertBody>,
+    caller: ReqData<ApiKey>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let (user_id, log_id) = path.into_inner();
+    // held for the whole revert so two requests can't both pass the already reverted check
+    let mut roblox_user = app_state.roblox_user.lock().await;
+    let mut user = match get_user_struct(user_id, &app_state).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().body(format!("No user found for {}", user_id)),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let batch_id = user.find_log(&log_id).and_then(|log| log.batch_id.clone());
+    let revert = match user.revert_log(
+        &log_id,
+        body.admin_id,
+        &caller.name,
+        app_state.config.points.total_policy,
+    ) {
+        Ok(revert) => revert,
+        Err(RevertError::NotFound) => {
+            return HttpResponse::NotFound()
+                .body(format!("No bP log {} for user {}", log_id, user_id))
+        }
+        Err(e) => return HttpResponse::Conflict().body(format!("{}: {}", log_id, e)),
+    };
+
+    let event = match &batch_id {
+        Some(batch_id) => match app_state.store.get_event(batch_id).await {
+            Ok(event) => event,
+            Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+        },
+        None => None,
+    };
+    let attended = attended_batch_event(event.as_ref(), user_id);
+    if attended {
+        user.events = user.events.saturating_sub(1);
+    }
+
+    if let Err(e) = app_state.store.put_user(&user).await {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+    if let Some(event) = event.filter(|_| attended) {
+        if let Err(e) = remove_from_event(&event, &[user_id], &app_state).await {
+            log_error(
+                &app_state.config.webhooks,
+                format!(
+                    "[{}] Failed to remove {} from event {}: {}",
+                    caller.name, user_id, event.id, e
+                ),
+            )
+            .await;
+        }
     }
 
-    ok_string += "";
+    log_to_discord(
+        &app_state.config.webhooks,
+        format!(
+            "[{}] Reverted {} bP award {} for {} - {}",
+            caller.name, -revert.amount, log_id, user.user_id, user.name
+        ),
+    )
+    .await;
+
+    check_promotion(&mut user, &app_state, &mut roblox_user, &caller.name).await;
+
+    HttpResponse::Ok().json(RevertResult {
+        user_id,
+        revert,
+        points: user.points,
+        rank: user.rank,
+    })
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum BatchRevertStatus {
+    Reverted,
+    AlreadyReverted,
+    StorageError,
+}
+
+#[derive(Serialize, Debug)]
+struct BatchRevertResult {
+    user_id: u64,
+    name: String,
+    status: BatchRevertStatus,
+    /// bP taken back from this user
+    amount: i32,
+    points: i32,
+    error: Option<String>,
+}
 
-    HttpResponse::Ok().json(ok_string)
+/// Reverts every award made by one call to the points endpoint
+#[post(
+    "users/points/batches/{batch_id}/revert",
+    wrap = "RequireScope(Scope::PointsWrite)"
+)]
+async fn revert_batch(
+    path: Path<String>,
+    body: Json<RevertBody>,
+    caller: ReqData<ApiKey>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let batch_id = path.into_inner();
+    let policy = app_state.config.points.total_policy;
+    let mut roblox_user = app_state.roblox_user.lock().await;
+
+    let users = match app_state.store.get_users_by_batch(&batch_id).await {
+        Ok(users) => users,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    if users.is_empty() {
+        return HttpResponse::NotFound().body(format!("No bP logs in batch {}", batch_id));
+    }
+    let event = match app_state.store.get_event(&batch_id).await {
+        Ok(event) => event,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let mut results: Vec<BatchRevertResult> = vec![];
+    let mut left_event: Vec<u64> = vec![];
+    for mut user in users {
+        user.ensure_log_ids();
+        let log_ids: Vec<String> = user
+            .bp_logs
+            .iter()
+            .flatten()
+            .filter(|log| log.batch_id.as_deref() == Some(batch_id.as_str()))
+            .filter_map(|log| log.id.clone())
+            .collect();
+
+        let mut amount = 0;
+        let mut reverted_any = false;
+        for log_id in log_ids {
+            if let Ok(revert) = user.revert_log(&log_id, body.admin_id, &caller.name, policy) {
+                amount -= revert.amount;
+                reverted_any = true;
+            }
+        }
+        let attended = reverted_any && attended_batch_event(event.as_ref(), user.user_id);
+        if attended {
+            user.events = user.events.saturating_sub(1);
+        }
+
+        let mut result = BatchRevertResult {
+            user_id: user.user_id,
+            name: user.name.clone(),
+            status: BatchRevertStatus::Reverted,
+            amount,
+            points: user.points,
+            error: None,
+        };
+        // a 0 bP award still gets a revert entry, so go by what was reverted rather than the amount
+        if !reverted_any {
+            result.status = BatchRevertStatus::AlreadyReverted;
+            results.push(result);
+            continue;
+        }
+
+        if let Err(e) = app_state.store.put_user(&user).await {
+            result.status = BatchRevertStatus::StorageError;
+            result.error = Some(e.to_string());
+            results.push(result);
+            continue;
+        }
+
+        if attended {
+            left_event.push(user.user_id);
+        }
+        check_promotion(&mut user, &app_state, &mut roblox_user, &caller.name).await;
+        result.points = user.points;
+        results.push(result);
+    }
+
+    if let Some(event) = event.filter(|_| !left_event.is_empty()) {
+        if let Err(e) = remove_from_event(&event, &left_event, &app_state).await {
+            log_error(
+                &app_state.config.webhooks,
+                format!(
+                    "[{}] Failed to update event {} after reverting its batch: {}",
+                    caller.name, event.id, e
+                ),
+            )
+            .await;
+        }
+    }
+
+    let reverted: Vec<&BatchRevertResult> = results
+        .iter()
+        .filter(|result| matches!(result.status, BatchRevertStatus::Reverted))
+        .collect();
+    let failed = results
+        .iter()
+        .filter(|result| matches!(result.status, BatchRevertStatus::StorageError))
+        .count();
+    log_to_discord(
+        &app_state.config.webhooks,
+        format!(
+            "[{}] Reverted batch {}: {} bP across {} users{}",
+            caller.name,
+            batch_id,
+            reverted.iter().map(|result| result.amount).sum::<i32>(),
+            reverted.len(),
+            if failed > 0 {
+                format!(", {} failed to save", failed)
+            } else {
+                String::new()
+            }
+        ),
+    )
+    .await;
+
+    if failed > 0 {
+        HttpResponse::MultiStatus().json(results)
+    } else {
+        HttpResponse::Ok().json(results)
+    }
 }
 
 pub fn configure_users(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_users);
+    cfg.service(get_user_by_name);
+    cfg.service(get_user_by_discord);
     cfg.service(get_user);
     cfg.service(create_user);
     cfg.service(increment_points);
+    cfg.service(prestige_user);
+    cfg.service(revert_bp_log);
+    cfg.service(revert_batch);
 }
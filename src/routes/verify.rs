@@ -1,16 +1,20 @@
 use std::time::SystemTime;
 
 use actix_web::{
-    get, post, put,
-    web::{Data, Json, Path, ServiceConfig},
+    delete, get, post, put,
+    web::{Data, Json, Path, Query, ReqData, ServiceConfig},
     HttpResponse,
 };
-use firebase_realtime_database::FirebaseError;
 use log::info;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    functions::verify::{get_verification_body, is_verified, VerificationBody, VerifiedStruct},
+    auth::{ApiKey, RequireScope, Scope},
+    definitions::verification::{VerificationBody, VerificationState},
+    functions::verify::{link_accounts, normalize_code, unlink_account, unused_code, LinkError},
+    logs::log_to_discord,
+    roblox::get_user_ids_from_usernames,
+    signing::RequireSignature,
     AppState,
 };
 
@@ -18,106 +22,397 @@ use crate::{
 struct Verification {
     discord_id: String,
     roblox_username: String,
+    /// Allows a verified discord user to move their link to another roblox account
+    #[serde(default)]
+    relink: bool,
 }
 
-/// Places a discord user in the verify/awaiting section
-/// Discord user provides their roblox username
-/// Roblox user joins the game then and the two are linked together
-#[put("verify")]
+/// A verification request along with the code it's stored under
+#[derive(Serialize, Debug)]
+struct PendingVerification {
+    code: String,
+    expires_at: SystemTime,
+    #[serde(flatten)]
+    body: VerificationBody,
+}
+
+impl PendingVerification {
+    fn new(code: String, body: VerificationBody, timeout_ms: u64) -> Self {
+        PendingVerification {
+            code,
+            expires_at: body.expires_at(timeout_ms),
+            body,
+        }
+    }
+}
+
+/// Issues a one-time code for a discord user to enter in game.
+/// The code is bound to the roblox account the username resolves to now,
+/// and replaces any code the discord user still had pending
+#[put("verify", wrap = "RequireScope(Scope::VerifyWrite)")]
 async fn request_verification(body: Json<Verification>, app_state: Data<AppState>) -> HttpResponse {
     info!("{:?}", body);
-    let database = &app_state.database.read();
+    let store = app_state.store.as_ref();
+
+    match store.get_verified(&body.discord_id).await {
+        Ok(Some(verified)) if !body.relink => {
+            return HttpResponse::Conflict().body(format!(
+                "{} is already verified as {}",
+                body.discord_id, verified.roblox_id
+            ))
+        }
+        Ok(_) => {}
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    }
+
+    let roblox_id = match get_user_ids_from_usernames(vec![body.roblox_username.clone()]).await {
+        Ok(user_ids) => user_ids
+            .into_iter()
+            .find(|(name, _)| name.to_lowercase() == body.roblox_username.to_lowercase())
+            .and_then(|(_, user_id)| user_id),
+        Err(e) => {
+            return HttpResponse::BadGateway()
+                .body(format!("Roblox failed to return user ids: {}", e))
+        }
+    };
+    let roblox_id = match roblox_id {
+        Some(roblox_id) => roblox_id,
+        None => {
+            return HttpResponse::NotFound()
+                .body(format!("No roblox user named {}", body.roblox_username))
+        }
+    };
+
+    match store.get_verified_by_roblox(roblox_id).await {
+        Ok(Some(verified)) => {
+            let message = if verified.discord_id == body.discord_id {
+                format!("{} is already verified as {}", body.discord_id, roblox_id)
+            } else {
+                LinkError::RobloxTaken(verified).to_string()
+            };
+            return HttpResponse::Conflict().body(message);
+        }
+        Ok(None) => {}
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    }
+
+    let awaiting = match store.get_awaiting_map().await {
+        Ok(awaiting) => awaiting,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    for (code, mut previous) in awaiting {
+        if previous.discord_id == body.discord_id && previous.state == VerificationState::Pending {
+            previous.state = VerificationState::Revoked;
+            if let Err(e) = store.put_awaiting(&code, &previous).await {
+                return HttpResponse::InternalServerError().body(e.to_string());
+            }
+        }
+    }
 
     let verification_body = VerificationBody {
         discord_id: body.discord_id.clone(),
         creation_time: SystemTime::now(),
+        roblox_id: Some(roblox_id),
+        state: VerificationState::Pending,
+    };
+    let code = match unused_code(store).await {
+        Ok(code) => code,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
     };
 
-    let verification_create_result = database
-        .put(
-            format!(
-                "verification/awaiting/{}",
-                body.roblox_username.to_lowercase()
-            )
-            .as_str(),
-            &verification_body,
-        )
-        .await;
+    let verification_create_result = store.put_awaiting(&code, &verification_body).await;
     info!("{:?}", verification_create_result);
 
     match verification_create_result {
-        Ok(response) => HttpResponse::Ok().body(response.text().await.unwrap()),
-        Err(err) => match err {
-            FirebaseError::GcpAuthError(e) => {
-                return HttpResponse::InternalServerError().body(format!("{:?}", e))
-            }
-            FirebaseError::ReqwestError(e) => {
-                return HttpResponse::InternalServerError().json(format!("{:?}", e))
-            }
-        },
+        Ok(()) => HttpResponse::Ok().json(PendingVerification::new(
+            code,
+            verification_body,
+            app_state.config.jobs.verification_timeout_ms,
+        )),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Pending code for a roblox account, so the game can ask the player to confirm it
+#[get(
+    "verify/pending/{roblox_id}",
+    wrap = "RequireScope(Scope::VerifyWrite)"
+)]
+async fn get_pending_verification(path: Path<u64>, app_state: Data<AppState>) -> HttpResponse {
+    let roblox_id = path.into_inner();
+    let timeout_ms = app_state.config.jobs.verification_timeout_ms;
+
+    let awaiting = match app_state.store.get_awaiting_map().await {
+        Ok(awaiting) => awaiting,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    let now = SystemTime::now();
+    let pending = awaiting
+        .into_iter()
+        .filter(|(_, body)| {
+            body.roblox_id == Some(roblox_id)
+                && body.state == VerificationState::Pending
+                && !body.is_expired(timeout_ms, now)
+        })
+        .max_by_key(|(_, body)| body.creation_time);
+
+    match pending {
+        Some((code, body)) => {
+            HttpResponse::Ok().json(PendingVerification::new(code, body, timeout_ms))
+        }
+        None => HttpResponse::NotFound().body(format!("No pending verification for {}", roblox_id)),
     }
 }
 
 #[derive(Deserialize)]
 struct RobloxVerification {
-    username: String,
+    code: String,
     user_id: u64,
 }
 
-/// Checks to see if a user who joined the roblox game is looking to be verified
-/// If they are, they are moved from the awaiting to the verified section
-/// Their roblox userid is logged
-#[post("verify")]
+/// Sent by the game when a player enters or confirms their code.
+/// The code must be pending, unexpired and issued for the player's roblox account.
+/// On success the discord and roblox accounts are linked, replacing an earlier link
+/// if the code was a relink, and the code is deleted
+#[post(
+    "verify",
+    wrap = "RequireSignature",
+    wrap = "RequireScope(Scope::VerifyWrite)"
+)]
 async fn check_verification(
     body: Json<RobloxVerification>,
+    caller: ReqData<ApiKey>,
     app_state: Data<AppState>,
 ) -> HttpResponse {
-    let database = &app_state.database.read();
+    let store = app_state.store.as_ref();
+    let code = normalize_code(&body.code);
 
-    let verification_option = get_verification_body::<VerificationBody>(
-        format!("verification/awaiting/{}", body.username.to_lowercase()).as_str(),
-        database,
+    let mut verification_body = match store.get_awaiting(&code).await {
+        Ok(Some(verification_body)) => verification_body,
+        Ok(None) => return HttpResponse::NotFound().body(format!("No verification code {}", code)),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    if verification_body.state == VerificationState::Pending
+        && verification_body.is_expired(
+            app_state.config.jobs.verification_timeout_ms,
+            SystemTime::now(),
+        )
+    {
+        verification_body.state = VerificationState::Expired;
+        if let Err(e) = store.put_awaiting(&code, &verification_body).await {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    }
+    if verification_body.state != VerificationState::Pending {
+        return HttpResponse::Conflict().body(format!(
+            "Verification code {} is {}",
+            code, verification_body.state
+        ));
+    }
+    if verification_body.roblox_id != Some(body.user_id) {
+        return HttpResponse::Conflict().body(format!(
+            "Verification code {} was issued for a different roblox account",
+            code
+        ));
+    }
+
+    let link_result = link_accounts(
+        store,
+        &verification_body.discord_id,
+        body.user_id,
+        &caller.name,
+        None,
     )
     .await;
-    if verification_option.is_none() {
-        return HttpResponse::InternalServerError()
-            .body("Firebase failed to read verification database");
+    match link_result {
+        Ok(_) => {}
+        Err(e @ LinkError::RobloxTaken(_)) => return HttpResponse::Conflict().body(e.to_string()),
+        Err(LinkError::Storage(e)) => {
+            return HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+    if let Err(e) = store.delete_awaiting(&code).await {
+        info!("failed to delete verification code {}: {}", code, e);
     }
 
-    let verification_body = verification_option.unwrap();
-    let verified_struct = VerifiedStruct {
-        roblox_id: body.user_id,
-        discord_id: verification_body.discord_id.clone(),
+    verification_body.state = VerificationState::Confirmed;
+    HttpResponse::Ok().json(PendingVerification::new(
+        code,
+        verification_body,
+        app_state.config.jobs.verification_timeout_ms,
+    ))
+}
+
+/// Revokes a pending code, e.g. when the discord user cancels
+#[delete("verify/codes/{code}", wrap = "RequireScope(Scope::VerifyWrite)")]
+async fn revoke_verification(path: Path<String>, app_state: Data<AppState>) -> HttpResponse {
+    let store = app_state.store.as_ref();
+    let code = normalize_code(&path.into_inner());
+
+    let mut verification_body = match store.get_awaiting(&code).await {
+        Ok(Some(verification_body)) => verification_body,
+        Ok(None) => return HttpResponse::NotFound().body(format!("No verification code {}", code)),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
     };
-    let _put_result = database
-        .put(
-            format!("verification/discord/{}", verification_body.discord_id).as_str(),
-            &verified_struct,
-        )
-        .await;
+    if verification_body.state != VerificationState::Pending {
+        return HttpResponse::Conflict().body(format!(
+            "Verification code {} is {}",
+            code, verification_body.state
+        ));
+    }
 
-    HttpResponse::Ok().body("Success!")
+    verification_body.state = VerificationState::Revoked;
+    match store.put_awaiting(&code, &verification_body).await {
+        Ok(()) => HttpResponse::Ok().json(PendingVerification::new(
+            code,
+            verification_body,
+            app_state.config.jobs.verification_timeout_ms,
+        )),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
 }
 
 /// Verification checker
 /// Gets the verification struct from the discord userid
-#[get("verify/{discord_id}")]
+#[get("verify/{discord_id}", wrap = "RequireScope(Scope::Read)")]
 async fn get_verification(path: Path<String>, app_state: Data<AppState>) -> HttpResponse {
-    let database = &app_state.database.read();
-
     let discord_user_id = path.into_inner();
-    let verification_option = is_verified(discord_user_id, database).await;
-    if verification_option.is_none() {
-        return HttpResponse::InternalServerError()
-            .body("Firebase failed to read verification database");
+
+    match app_state.store.get_verified(&discord_user_id).await {
+        Ok(Some(verified_struct)) => HttpResponse::Ok().json(verified_struct),
+        Ok(None) => HttpResponse::NotFound().body(format!("{} is not verified", discord_user_id)),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct UnlinkQuery {
+    reason: Option<String>,
+}
+
+/// Removes a discord user's link, e.g. for moderation or before they verify a new account
+#[delete("verify/{discord_id}", wrap = "RequireScope(Scope::VerifyWrite)")]
+async fn delete_verification(
+    path: Path<String>,
+    query: Query<UnlinkQuery>,
+    caller: ReqData<ApiKey>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let discord_id = path.into_inner();
+
+    let unlink_result = unlink_account(
+        app_state.store.as_ref(),
+        &discord_id,
+        &caller.name,
+        query.reason.as_deref(),
+    )
+    .await;
+    match unlink_result {
+        Ok(Some(previous)) => {
+            log_to_discord(
+                &app_state.config.webhooks,
+                format!(
+                    "[{}] Unlinked {} from roblox account {}",
+                    caller.name, discord_id, previous.roblox_id
+                ),
+            )
+            .await;
+            HttpResponse::Ok().json(previous)
+        }
+        Ok(None) => HttpResponse::NotFound().body(format!("{} is not verified", discord_id)),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Relink {
+    roblox_id: u64,
+    reason: Option<String>,
+}
+
+/// Points a discord user's link at another roblox account without a code, for moderators
+#[put("verify/{discord_id}", wrap = "RequireScope(Scope::VerifyWrite)")]
+async fn relink_verification(
+    path: Path<String>,
+    body: Json<Relink>,
+    caller: ReqData<ApiKey>,
+    app_state: Data<AppState>,
+) -> HttpResponse {
+    let discord_id = path.into_inner();
+
+    let link_result = link_accounts(
+        app_state.store.as_ref(),
+        &discord_id,
+        body.roblox_id,
+        &caller.name,
+        body.reason.as_deref(),
+    )
+    .await;
+    match link_result {
+        Ok(verified) => {
+            log_to_discord(
+                &app_state.config.webhooks,
+                format!(
+                    "[{}] Linked {} to roblox account {}",
+                    caller.name, discord_id, verified.roblox_id
+                ),
+            )
+            .await;
+            HttpResponse::Ok().json(verified)
+        }
+        Err(e @ LinkError::RobloxTaken(_)) => HttpResponse::Conflict().body(e.to_string()),
+        Err(LinkError::Storage(e)) => HttpResponse::InternalServerError().body(e.to_string()),
     }
+}
+
+/// Discord id linked to a roblox account
+#[get("verify/roblox/{roblox_id}", wrap = "RequireScope(Scope::Read)")]
+async fn get_verification_by_roblox(path: Path<u64>, app_state: Data<AppState>) -> HttpResponse {
+    let roblox_id = path.into_inner();
+
+    match app_state.store.get_verified_by_roblox(roblox_id).await {
+        Ok(Some(verified_struct)) => HttpResponse::Ok().json(verified_struct),
+        Ok(None) => HttpResponse::NotFound().body(format!("{} is not linked", roblox_id)),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct HistoryQuery {
+    discord_id: Option<String>,
+    roblox_id: Option<u64>,
+}
+
+/// Past links and unlinks, oldest first, optionally for one discord id or roblox account
+#[get("verify/history", wrap = "RequireScope(Scope::Read)")]
+async fn get_link_history(query: Query<HistoryQuery>, app_state: Data<AppState>) -> HttpResponse {
+    let mut records = match app_state.store.get_link_records().await {
+        Ok(records) => records,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    records.retain(|record| {
+        query
+            .discord_id
+            .as_ref()
+            .is_none_or(|discord_id| &record.discord_id == discord_id)
+            && query
+                .roblox_id
+                .is_none_or(|roblox_id| record.roblox_id == roblox_id)
+    });
 
-    let verified_struct = verification_option.unwrap();
-    HttpResponse::Ok().json(verified_struct)
+    HttpResponse::Ok().json(records)
 }
 
 pub fn configure_verify(cfg: &mut ServiceConfig) {
     cfg.service(request_verification);
     cfg.service(check_verification);
+    cfg.service(get_pending_verification);
+    cfg.service(revoke_verification);
+    cfg.service(get_verification_by_roblox);
+    // Before `get_verification`, which would otherwise take "history" as a discord id
+    cfg.service(get_link_history);
     cfg.service(get_verification);
+    cfg.service(delete_verification);
+    cfg.service(relink_verification);
 }
@@ -0,0 +1,264 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    future::{ready, Future, Ready},
+    io::Read,
+    pin::Pin,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    web::{Bytes, Data},
+    Error, HttpResponse,
+};
+use hmac::{Hmac, Mac};
+use log::info;
+use parking_lot::Mutex;
+use sha2::Sha256;
+
+use crate::{logs::log_error, AppState};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SIGNATURE_HEADER: &str = "X-Signature";
+const TIMESTAMP_HEADER: &str = "X-Signature-Timestamp";
+const NONCE_HEADER: &str = "X-Signature-Nonce";
+
+/// Verifies requests signed by our roblox game servers.
+/// The signature is a hex encoded HMAC-SHA256 over `{timestamp}.{nonce}.{body}`
+pub struct RequestSigner {
+    secret: Vec<u8>,
+    max_skew: u64,
+    /// Nonces seen inside the skew window, with the timestamp they were signed at
+    nonces: Mutex<HashMap<String, u64>>,
+}
+
+impl RequestSigner {
+    pub fn new(secret: Vec<u8>, max_skew: u64) -> Self {
+        RequestSigner {
+            secret,
+            max_skew,
+            nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_file(path: &str, max_skew: u64) -> anyhow::Result<Self> {
+        let mut secret_file = File::open(path)?;
+        let mut secret = String::new();
+        secret_file.read_to_string(&mut secret)?;
+
+        Ok(RequestSigner::new(
+            secret.trim().as_bytes().to_vec(),
+            max_skew,
+        ))
+    }
+
+    pub fn verify(
+        &self,
+        timestamp: &str,
+        nonce: &str,
+        signature: &str,
+        body: &[u8],
+    ) -> Result<(), String> {
+        let signed_at: u64 = timestamp
+            .parse()
+            .map_err(|_| format!("invalid timestamp {}", timestamp))?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if now.abs_diff(signed_at) > self.max_skew {
+            return Err(format!(
+                "timestamp {} is outside the allowed skew",
+                signed_at
+            ));
+        }
+
+        let signature = hex::decode(signature).map_err(|_| "signature is not hex".to_string())?;
+        let mut mac = HmacSha256::new_from_slice(&self.secret).map_err(|e| e.to_string())?;
+        mac.update(timestamp.as_bytes());
+        mac.update(b".");
+        mac.update(nonce.as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        mac.verify_slice(&signature)
+            .map_err(|_| "signature mismatch".to_string())?;
+
+        // only remember nonces once the signature checks out, so garbage can't fill the cache
+        let mut nonces = self.nonces.lock();
+        nonces.retain(|_, seen_at| now.abs_diff(*seen_at) <= self.max_skew);
+        if nonces.insert(nonce.to_string(), signed_at).is_some() {
+            return Err(format!("nonce {} was already used", nonce));
+        }
+
+        Ok(())
+    }
+}
+
+/// Route middleware that rejects requests not signed with the game server secret
+/// List it before `RequireScope` in `wrap`: the last wrap runs first, and rejected
+/// signatures are reported to the error webhook, which unauthenticated callers shouldn't reach
+pub struct RequireSignature;
+
+impl<S, B> Transform<S, ServiceRequest> for RequireSignature
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RequireSignatureMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireSignatureMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequireSignatureMiddleware<S> {
+    service: Rc<S>,
+}
+
+fn header<'a>(req: &'a ServiceRequest, name: &str) -> Result<&'a str, String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(format!("missing {} header", name))
+}
+
+impl<S, B> Service<ServiceRequest> for RequireSignatureMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let body = req.extract::<Bytes>().await?;
+
+            let app_state = req.app_data::<Data<AppState>>().cloned();
+            let result = match &app_state {
+                Some(app_state) => header(&req, TIMESTAMP_HEADER).and_then(|timestamp| {
+                    let nonce = header(&req, NONCE_HEADER)?;
+                    let signature = header(&req, SIGNATURE_HEADER)?;
+                    app_state.signer.verify(timestamp, nonce, signature, &body)
+                }),
+                None => Err("signer is not configured".to_string()),
+            };
+
+            if let Err(reason) = result {
+                let peer = req
+                    .connection_info()
+                    .realip_remote_addr()
+                    .unwrap_or("unknown")
+                    .to_string();
+                let message = format!(
+                    "Rejected unsigned request to {} from {}: {}",
+                    req.path(),
+                    peer,
+                    reason
+                );
+                match &app_state {
+                    Some(app_state) => log_error(&app_state.config.webhooks, message).await,
+                    None => info!("{}", message),
+                }
+
+                let response = HttpResponse::Unauthorized().body(reason);
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+
+            req.set_payload(Payload::from(body));
+            Ok(service.call(req).await?.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"game server secret";
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn sign(timestamp: &str, nonce: &str, body: &[u8]) -> String {
+        let mut mac = HmacSha256::new_from_slice(SECRET).unwrap();
+        mac.update(format!("{}.{}.", timestamp, nonce).as_bytes());
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn accepts_a_fresh_signature() {
+        let signer = RequestSigner::new(SECRET.to_vec(), 300);
+        let timestamp = now().to_string();
+        let signature = sign(&timestamp, "a", b"{}");
+
+        assert_eq!(signer.verify(&timestamp, "a", &signature, b"{}"), Ok(()));
+    }
+
+    #[test]
+    fn rejects_a_tampered_body_or_wrong_secret() {
+        let signer = RequestSigner::new(SECRET.to_vec(), 300);
+        let timestamp = now().to_string();
+        let signature = sign(&timestamp, "a", b"{}");
+
+        assert!(signer
+            .verify(&timestamp, "a", &signature, b"{\"x\":1}")
+            .is_err());
+        let other = RequestSigner::new(b"other".to_vec(), 300);
+        assert!(other.verify(&timestamp, "a", &signature, b"{}").is_err());
+    }
+
+    #[test]
+    fn rejects_timestamps_outside_the_skew() {
+        let signer = RequestSigner::new(SECRET.to_vec(), 300);
+        for timestamp in [now() - 301, now() + 301] {
+            let timestamp = timestamp.to_string();
+            let signature = sign(&timestamp, "a", b"{}");
+
+            assert!(signer.verify(&timestamp, "a", &signature, b"{}").is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_a_replayed_nonce() {
+        let signer = RequestSigner::new(SECRET.to_vec(), 300);
+        let timestamp = now().to_string();
+        let signature = sign(&timestamp, "a", b"{}");
+
+        assert!(signer.verify(&timestamp, "a", &signature, b"{}").is_ok());
+        assert!(signer.verify(&timestamp, "a", &signature, b"{}").is_err());
+
+        let signature = sign(&timestamp, "b", b"{}");
+        assert!(signer.verify(&timestamp, "b", &signature, b"{}").is_ok());
+    }
+
+    #[test]
+    fn bad_signature_does_not_burn_the_nonce() {
+        let signer = RequestSigner::new(SECRET.to_vec(), 300);
+        let timestamp = now().to_string();
+
+        assert!(signer.verify(&timestamp, "a", "00", b"{}").is_err());
+        let signature = sign(&timestamp, "a", b"{}");
+        assert!(signer.verify(&timestamp, "a", &signature, b"{}").is_ok());
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use firebase_realtime_database::Database;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{EventStore, StorageError, UserStore, VerificationStore};
+use crate::definitions::{
+    events::Event,
+    users::User,
+    verification::{LinkRecord, VerificationBody, VerifiedStruct},
+};
+
+pub struct FirebaseStore {
+    database: Database,
+}
+
+impl FirebaseStore {
+    pub fn new(database: Database) -> Self {
+        FirebaseStore { database }
+    }
+
+    /// Firebase answers `null` for paths that don't exist, so every read is an `Option`
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<Option<T>, StorageError> {
+        let response = self.database.get(path).await?;
+        response
+            .json::<Option<T>>()
+            .await
+            .map_err(StorageError::Deserialize)
+    }
+
+    async fn put<T: Serialize + Sync>(&self, path: &str, body: &T) -> Result<(), StorageError> {
+        self.database.put(path, body).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), StorageError> {
+        self.database.delete(path).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserStore for FirebaseStore {
+    async fn get_user(&self, user_id: u64) -> Result<Option<User>, StorageError> {
+        self.get::<User>(format!("users/{}", user_id).as_str())
+            .await
+    }
+
+    async fn put_user(&self, user: &User) -> Result<(), StorageError> {
+        self.put(format!("users/{}", user.user_id).as_str(), user)
+            .await
+    }
+
+    async fn get_users(&self) -> Result<Vec<User>, StorageError> {
+        let user_map = self
+            .get::<HashMap<String, User>>("users")
+            .await?
+            .unwrap_or_default();
+
+        Ok(user_map.into_values().collect())
+    }
+}
+
+#[async_trait]
+impl VerificationStore for FirebaseStore {
+    async fn get_awaiting(&self, code: &str) -> Result<Option<VerificationBody>, StorageError> {
+        self.get(format!("verification/awaiting/{}", code).as_str())
+            .await
+    }
+
+    async fn get_awaiting_map(&self) -> Result<HashMap<String, VerificationBody>, StorageError> {
+        Ok(self
+            .get::<HashMap<String, VerificationBody>>("verification/awaiting/")
+            .await?
+            .unwrap_or_default())
+    }
+
+    async fn put_awaiting(&self, code: &str, body: &VerificationBody) -> Result<(), StorageError> {
+        self.put(format!("verification/awaiting/{}", code).as_str(), body)
+            .await
+    }
+
+    async fn delete_awaiting(&self, code: &str) -> Result<(), StorageError> {
+        self.delete(format!("verification/awaiting/{}", code).as_str())
+            .await
+    }
+
+    async fn get_verified(&self, discord_id: &str) -> Result<Option<VerifiedStruct>, StorageError> {
+        self.get(format!("verification/discord/{}", discord_id).as_str())
+            .await
+    }
+
+    /// Also maintains the reverse index under `verification/roblox/{roblox_id}`.
+    /// Firebase has no transactions here, so the uniqueness check is best effort
+    async fn put_verified(&self, verified: &VerifiedStruct) -> Result<(), StorageError> {
+        if let Some(existing) = self.get_verified_by_roblox(verified.roblox_id).await? {
+            if existing.discord_id != verified.discord_id {
+                return Err(StorageError::Conflict(format!(
+                    "roblox account {} is already linked to {}",
+                    existing.roblox_id, existing.discord_id
+                )));
+            }
+        }
+        let previous = self.get_verified(&verified.discord_id).await?;
+
+        self.put(
+            format!("verification/discord/{}", verified.discord_id).as_str(),
+            verified,
+        )
+        .await?;
+        self.put(
+            format!("verification/roblox/{}", verified.roblox_id).as_str(),
+            &verified.discord_id,
+        )
+        .await?;
+
+        if let Some(previous) = previous.filter(|previous| previous.roblox_id != verified.roblox_id)
+        {
+            let old_index = format!("verification/roblox/{}", previous.roblox_id);
+            let indexed = self.get::<String>(old_index.as_str()).await?;
+            if indexed.as_deref() == Some(verified.discord_id.as_str()) {
+                self.delete(old_index.as_str()).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn delete_verified(&self, discord_id: &str) -> Result<(), StorageError> {
+        if let Some(verified) = self.get_verified(discord_id).await? {
+            let indexed = self
+                .get::<String>(format!("verification/roblox/{}", verified.roblox_id).as_str())
+                .await?;
+            if indexed.as_deref() == Some(discord_id) {
+                self.delete(format!("verification/roblox/{}", verified.roblox_id).as_str())
+                    .await?;
+            }
+        }
+        self.delete(format!("verification/discord/{}", discord_id).as_str())
+            .await
+    }
+
+    /// Links made before the index existed aren't in it, so a miss falls back to a scan
+    async fn get_verified_by_roblox(
+        &self,
+        roblox_id: u64,
+    ) -> Result<Option<VerifiedStruct>, StorageError> {
+        let indexed = self
+            .get::<String>(format!("verification/roblox/{}", roblox_id).as_str())
+            .await?;
+        if let Some(discord_id) = indexed {
+            if let Some(verified) = self.get_verified(&discord_id).await? {
+                if verified.roblox_id == roblox_id {
+                    return Ok(Some(verified));
+                }
+            }
+        }
+
+        let verified_map = self
+            .get::<HashMap<String, VerifiedStruct>>("verification/discord")
+            .await?
+            .unwrap_or_default();
+        Ok(verified_map
+            .into_values()
+            .find(|verified| verified.roblox_id == roblox_id))
+    }
+
+    async fn add_link_record(&self, record: &LinkRecord) -> Result<(), StorageError> {
+        let record_id = format!("{:016x}", rand::random::<u64>());
+        self.put(
+            format!("verification/history/{}", record_id).as_str(),
+            record,
+        )
+        .await
+    }
+
+    async fn get_link_records(&self) -> Result<Vec<LinkRecord>, StorageError> {
+        let mut records: Vec<LinkRecord> = self
+            .get::<HashMap<String, LinkRecord>>("verification/history")
+            .await?
+            .unwrap_or_default()
+            .into_values()
+            .collect();
+        records.sort_by_key(|record| record.time);
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl EventStore for FirebaseStore {
+    async fn get_event(&self, event_id: &str) -> Result<Option<Event>, StorageError> {
+        self.get(format!("events/{}", event_id).as_str()).await
+    }
+
+    async fn put_event(&self, event: &Event) -> Result<(), StorageError> {
+        self.put(format!("events/{}", event.id).as_str(), event)
+            .await
+    }
+
+    async fn delete_event(&self, event_id: &str) -> Result<(), StorageError> {
+        self.delete(format!("events/{}", event_id).as_str()).await
+    }
+
+    async fn get_events(&self) -> Result<Vec<Event>, StorageError> {
+        let event_map = self
+            .get::<HashMap<String, Event>>("events")
+            .await?
+            .unwrap_or_default();
+
+        let mut events: Vec<Event> = event_map.into_values().collect();
+        events.sort_by_key(|event| std::cmp::Reverse(event.start_time));
+        Ok(events)
+    }
+}
@@ -0,0 +1,64 @@
+use std::{collections::HashMap, fs::File, io::BufReader};
+
+use serde::Deserialize;
+
+use super::SqliteStore;
+use crate::definitions::{
+    users::User,
+    verification::{VerificationBody, VerifiedStruct},
+};
+
+#[derive(Deserialize, Default)]
+struct FirebaseVerification {
+    #[serde(default)]
+    awaiting: HashMap<String, VerificationBody>,
+    #[serde(default)]
+    discord: HashMap<String, VerifiedStruct>,
+}
+
+/// Shape of the JSON file produced by "Export JSON" on the root of the realtime database
+#[derive(Deserialize)]
+struct FirebaseExport {
+    #[serde(default)]
+    users: HashMap<String, User>,
+    #[serde(default)]
+    verification: FirebaseVerification,
+}
+
+#[derive(Debug)]
+pub struct ImportSummary {
+    pub users: usize,
+    pub awaiting: usize,
+    pub verified: usize,
+    /// Links skipped because their roblox account was already linked
+    pub duplicate_links: usize,
+}
+
+/// One-shot copy of a firebase JSON export into a sqlite database
+pub fn import_firebase_export(path: &str, store: &SqliteStore) -> anyhow::Result<ImportSummary> {
+    let reader = BufReader::new(File::open(path)?);
+    let export: FirebaseExport = serde_json::from_reader(reader)?;
+
+    let users: Vec<User> = export
+        .users
+        .into_iter()
+        .map(|(key, mut user)| {
+            // older records were written without a user_id, the key is the source of truth
+            if user.user_id == 0 {
+                user.user_id = key.parse().unwrap_or_default();
+            }
+            user
+        })
+        .filter(|user| user.user_id != 0)
+        .collect();
+    let verified: Vec<VerifiedStruct> = export.verification.discord.into_values().collect();
+
+    let duplicate_links = store.import(&users, &export.verification.awaiting, &verified)?;
+
+    Ok(ImportSummary {
+        users: users.len(),
+        awaiting: export.verification.awaiting.len(),
+        verified: verified.len() - duplicate_links,
+        duplicate_links,
+    })
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use parking_lot::RwLock;
+
+use super::{EventStore, StorageError, UserStore, VerificationStore};
+use crate::definitions::{
+    events::Event,
+    users::User,
+    verification::{LinkRecord, VerificationBody, VerifiedStruct},
+};
+
+/// Process-local backend, everything is lost on restart.
+/// Lets the server run without any GCP credentials
+#[derive(Default)]
+pub struct MemoryStore {
+    users: RwLock<HashMap<u64, User>>,
+    awaiting: RwLock<HashMap<String, VerificationBody>>,
+    verified: RwLock<HashMap<String, VerifiedStruct>>,
+    link_records: RwLock<Vec<LinkRecord>>,
+    events: RwLock<HashMap<String, Event>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryStore {
+    async fn get_user(&self, user_id: u64) -> Result<Option<User>, StorageError> {
+        Ok(self.users.read().get(&user_id).cloned())
+    }
+
+    async fn put_user(&self, user: &User) -> Result<(), StorageError> {
+        self.users.write().insert(user.user_id, user.clone());
+        Ok(())
+    }
+
+    async fn get_users(&self) -> Result<Vec<User>, StorageError> {
+        Ok(self.users.read().values().cloned().collect())
+    }
+}
+
+#[async_trait]
+impl VerificationStore for MemoryStore {
+    async fn get_awaiting(&self, code: &str) -> Result<Option<VerificationBody>, StorageError> {
+        Ok(self.awaiting.read().get(code).cloned())
+    }
+
+    async fn get_awaiting_map(&self) -> Result<HashMap<String, VerificationBody>, StorageError> {
+        Ok(self.awaiting.read().clone())
+    }
+
+    async fn put_awaiting(&self, code: &str, body: &VerificationBody) -> Result<(), StorageError> {
+        self.awaiting.write().insert(code.to_string(), body.clone());
+        Ok(())
+    }
+
+    async fn delete_awaiting(&self, code: &str) -> Result<(), StorageError> {
+        self.awaiting.write().remove(code);
+        Ok(())
+    }
+
+    async fn get_verified(&self, discord_id: &str) -> Result<Option<VerifiedStruct>, StorageError> {
+        Ok(self.verified.read().get(discord_id).cloned())
+    }
+
+    async fn put_verified(&self, verified: &VerifiedStruct) -> Result<(), StorageError> {
+        let mut links = self.verified.write();
+        if let Some(existing) = links.values().find(|existing| {
+            existing.roblox_id == verified.roblox_id && existing.discord_id != verified.discord_id
+        }) {
+            return Err(StorageError::Conflict(format!(
+                "roblox account {} is already linked to {}",
+                existing.roblox_id, existing.discord_id
+            )));
+        }
+
+        links.insert(verified.discord_id.clone(), verified.clone());
+        Ok(())
+    }
+
+    async fn delete_verified(&self, discord_id: &str) -> Result<(), StorageError> {
+        self.verified.write().remove(discord_id);
+        Ok(())
+    }
+
+    async fn get_verified_by_roblox(
+        &self,
+        roblox_id: u64,
+    ) -> Result<Option<VerifiedStruct>, StorageError> {
+        Ok(self
+            .verified
+            .read()
+            .values()
+            .find(|verified| verified.roblox_id == roblox_id)
+            .cloned())
+    }
+
+    async fn add_link_record(&self, record: &LinkRecord) -> Result<(), StorageError> {
+        self.link_records.write().push(record.clone());
+        Ok(())
+    }
+
+    async fn get_link_records(&self) -> Result<Vec<LinkRecord>, StorageError> {
+        Ok(self.link_records.read().clone())
+    }
+}
+
+#[async_trait]
+impl EventStore for MemoryStore {
+    async fn get_event(&self, event_id: &str) -> Result<Option<Event>, StorageError> {
+        Ok(self.events.read().get(event_id).cloned())
+    }
+
+    async fn put_event(&self, event: &Event) -> Result<(), StorageError> {
+        self.events.write().insert(event.id.clone(), event.clone());
+        Ok(())
+    }
+
+    async fn delete_event(&self, event_id: &str) -> Result<(), StorageError> {
+        self.events.write().remove(event_id);
+        Ok(())
+    }
+
+    async fn get_events(&self) -> Result<Vec<Event>, StorageError> {
+        let mut events: Vec<Event> = self.events.read().values().cloned().collect();
+        events.sort_by_key(|event| std::cmp::Reverse(event.start_time));
+        Ok(events)
+    }
+}
@@ -0,0 +1,196 @@
+use std::{collections::HashMap, fmt};
+
+use async_trait::async_trait;
+use firebase_realtime_database::FirebaseError;
+
+use crate::definitions::{
+    events::Event,
+    users::{Division, User},
+    verification::{LinkRecord, VerificationBody, VerifiedStruct},
+};
+
+pub mod firebase;
+pub mod import;
+pub mod memory;
+pub mod sqlite;
+
+pub use firebase::FirebaseStore;
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Firebase(FirebaseError),
+    Deserialize(reqwest::Error),
+    Sqlite(rusqlite::Error),
+    /// A write would break a uniqueness rule, e.g. a second link to one roblox account
+    Conflict(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Firebase(FirebaseError::GcpAuthError(e)) => write!(f, "{:?}", e),
+            StorageError::Firebase(FirebaseError::ReqwestError(e)) => write!(f, "{:?}", e),
+            StorageError::Deserialize(e) => write!(f, "{}", e),
+            StorageError::Sqlite(e) => write!(f, "{}", e),
+            StorageError::Conflict(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<FirebaseError> for StorageError {
+    fn from(err: FirebaseError) -> Self {
+        StorageError::Firebase(err)
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(err: rusqlite::Error) -> Self {
+        StorageError::Sqlite(err)
+    }
+}
+
+/// Criteria for `UserStore::list_users`, unset fields match everyone
+#[derive(Debug, Clone, Default)]
+pub struct UserFilter {
+    pub rank: Option<String>,
+    pub division: Option<Division>,
+    pub min_points: Option<i32>,
+    /// Case-insensitive
+    pub name_prefix: Option<String>,
+}
+
+impl UserFilter {
+    pub fn matches(&self, user: &User) -> bool {
+        self.rank.as_ref().is_none_or(|rank| &user.rank == rank)
+            && self.division.is_none_or(|division| {
+                user.divisions
+                    .as_ref()
+                    .is_some_and(|divisions| divisions.rank_in(division).is_some())
+            })
+            && self.min_points.is_none_or(|min| user.points >= min)
+            && self
+                .name_prefix
+                .as_ref()
+                .is_none_or(|prefix| user.name.to_lowercase().starts_with(&prefix.to_lowercase()))
+    }
+}
+
+/// Persistence for `User` records, keyed by roblox id
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    async fn get_user(&self, user_id: u64) -> Result<Option<User>, StorageError>;
+    async fn put_user(&self, user: &User) -> Result<(), StorageError>;
+    async fn get_users(&self) -> Result<Vec<User>, StorageError>;
+
+    /// All users without their bP logs, highest points first, for the leaderboard.
+    /// Backends that can sort on their side should override this
+    async fn get_users_by_points(&self) -> Result<Vec<User>, StorageError> {
+        let mut users = self.get_users().await?;
+        users.sort_by_key(|user| std::cmp::Reverse(user.points));
+        for user in users.iter_mut() {
+            user.bp_logs = None;
+        }
+        Ok(users)
+    }
+
+    /// Users with at least one bP log written by the points call `batch_id`
+    async fn get_users_by_batch(&self, batch_id: &str) -> Result<Vec<User>, StorageError> {
+        let mut users = self.get_users().await?;
+        users.retain(|user| {
+            user.bp_logs
+                .iter()
+                .flatten()
+                .any(|log| log.batch_id.as_deref() == Some(batch_id))
+        });
+        Ok(users)
+    }
+
+    /// Up to `limit` users matching `filter` with a roblox id above `after`, lowest id first
+    async fn list_users(
+        &self,
+        filter: &UserFilter,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<User>, StorageError> {
+        let mut users = self.get_users().await?;
+        users.retain(|user| after.is_none_or(|after| user.user_id > after) && filter.matches(user));
+        users.sort_by_key(|user| user.user_id);
+        users.truncate(limit);
+        Ok(users)
+    }
+
+    /// User whose stored roblox name matches `name`, ignoring case
+    async fn find_user_by_name(&self, name: &str) -> Result<Option<User>, StorageError> {
+        let name = name.to_lowercase();
+        Ok(self
+            .get_users()
+            .await?
+            .into_iter()
+            .find(|user| user.name.to_lowercase() == name))
+    }
+}
+
+/// Persistence for verification requests (keyed by their one-time code)
+/// and completed verifications (keyed by discord id)
+#[async_trait]
+pub trait VerificationStore: Send + Sync {
+    async fn get_awaiting(&self, code: &str) -> Result<Option<VerificationBody>, StorageError>;
+    async fn get_awaiting_map(&self) -> Result<HashMap<String, VerificationBody>, StorageError>;
+    async fn put_awaiting(&self, code: &str, body: &VerificationBody) -> Result<(), StorageError>;
+    async fn delete_awaiting(&self, code: &str) -> Result<(), StorageError>;
+
+    async fn get_verified(&self, discord_id: &str) -> Result<Option<VerifiedStruct>, StorageError>;
+    /// Creates or moves the link for `verified.discord_id`. Fails with `StorageError::Conflict`
+    /// when the roblox account is already linked to another discord id
+    async fn put_verified(&self, verified: &VerifiedStruct) -> Result<(), StorageError>;
+    async fn delete_verified(&self, discord_id: &str) -> Result<(), StorageError>;
+
+    /// Link for a roblox account, through the `roblox_id → discord_id` index
+    async fn get_verified_by_roblox(
+        &self,
+        roblox_id: u64,
+    ) -> Result<Option<VerifiedStruct>, StorageError>;
+
+    async fn add_link_record(&self, record: &LinkRecord) -> Result<(), StorageError>;
+
+    /// Every link change, oldest first
+    async fn get_link_records(&self) -> Result<Vec<LinkRecord>, StorageError>;
+}
+
+/// Persistence for hosted events, keyed by event id
+#[async_trait]
+pub trait EventStore: Send + Sync {
+    async fn get_event(&self, event_id: &str) -> Result<Option<Event>, StorageError>;
+    async fn put_event(&self, event: &Event) -> Result<(), StorageError>;
+    async fn delete_event(&self, event_id: &str) -> Result<(), StorageError>;
+
+    /// Takes a user off an event's attendee list, used when their award is reverted
+    async fn remove_attendee(&self, event_id: &str, user_id: u64) -> Result<(), StorageError> {
+        if let Some(mut event) = self.get_event(event_id).await? {
+            event
+                .attendees
+                .retain(|attendee| attendee.user_id != user_id);
+            self.put_event(&event).await?;
+        }
+        Ok(())
+    }
+
+    /// All events, most recent first
+    async fn get_events(&self) -> Result<Vec<Event>, StorageError>;
+
+    /// Events the user attended, most recent first
+    async fn get_user_events(&self, user_id: u64) -> Result<Vec<Event>, StorageError> {
+        let mut events = self.get_events().await?;
+        events.retain(|event| event.attended(user_id));
+        Ok(events)
+    }
+}
+
+/// Everything the app needs from a backend, so `AppState` can hold a single trait object
+pub trait Storage: UserStore + VerificationStore + EventStore {}
+
+impl<T: UserStore + VerificationStore + EventStore> Storage for T {}
@@ -0,0 +1,1055 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Row};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{EventStore, StorageError, UserFilter, UserStore, VerificationStore};
+use crate::definitions::{
+    events::{Attendee, Event},
+    users::{BPLog, Division, Divisions, LeaveOfAbsence, User},
+    verification::{LinkAction, LinkRecord, VerificationBody, VerificationState, VerifiedStruct},
+};
+
+/// Schema changes, applied in order and tracked through `PRAGMA user_version`.
+/// Never edit an entry once it has shipped, append a new one instead
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE users (
+        user_id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        points INTEGER NOT NULL DEFAULT 0,
+        total_points INTEGER NOT NULL DEFAULT 0,
+        events INTEGER NOT NULL DEFAULT 0,
+        floor_points INTEGER,
+        goal_points INTEGER,
+        rank TEXT NOT NULL,
+        prestige INTEGER
+    );
+    CREATE INDEX users_points ON users (points DESC);
+
+    CREATE TABLE divisions (
+        user_id INTEGER PRIMARY KEY REFERENCES users (user_id) ON DELETE CASCADE,
+        st TEXT,
+        sable TEXT
+    );
+
+    CREATE TABLE bp_logs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        time TEXT NOT NULL,
+        awarder INTEGER NOT NULL,
+        amount INTEGER NOT NULL,
+        place_name TEXT
+    );
+    CREATE INDEX bp_logs_user ON bp_logs (user_id, position);
+
+    CREATE TABLE verification_awaiting (
+        roblox_username TEXT PRIMARY KEY,
+        discord_id TEXT NOT NULL,
+        creation_time INTEGER NOT NULL
+    );
+
+    CREATE TABLE verification_discord (
+        discord_id TEXT PRIMARY KEY,
+        roblox_id INTEGER NOT NULL
+    );
+"#,
+    r#"
+    ALTER TABLE bp_logs ADD COLUMN api_key TEXT;
+"#,
+    r#"
+    ALTER TABLE bp_logs ADD COLUMN log_id TEXT;
+    ALTER TABLE bp_logs ADD COLUMN reverts TEXT;
+"#,
+    r#"
+    ALTER TABLE bp_logs ADD COLUMN batch_id TEXT;
+    CREATE INDEX bp_logs_batch ON bp_logs (batch_id);
+"#,
+    r#"
+    CREATE TABLE events (
+        id TEXT PRIMARY KEY,
+        host_id INTEGER NOT NULL,
+        place_name TEXT,
+        event_type TEXT NOT NULL,
+        start_time INTEGER NOT NULL,
+        end_time INTEGER,
+        batch_id TEXT,
+        api_key TEXT
+    );
+    CREATE INDEX events_start ON events (start_time DESC);
+
+    CREATE TABLE event_attendees (
+        event_id TEXT NOT NULL REFERENCES events (id) ON DELETE CASCADE,
+        user_id INTEGER NOT NULL,
+        points INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (event_id, user_id)
+    );
+    CREATE INDEX event_attendees_user ON event_attendees (user_id);
+"#,
+    r#"
+    CREATE TABLE leaves (
+        user_id INTEGER PRIMARY KEY REFERENCES users (user_id) ON DELETE CASCADE,
+        start_time INTEGER NOT NULL,
+        end_time INTEGER NOT NULL,
+        reason TEXT,
+        granted_by INTEGER NOT NULL
+    );
+"#,
+    r#"
+    ALTER TABLE verification_awaiting RENAME COLUMN roblox_username TO code;
+    ALTER TABLE verification_awaiting ADD COLUMN roblox_id INTEGER;
+    ALTER TABLE verification_awaiting ADD COLUMN state TEXT NOT NULL DEFAULT 'pending';
+"#,
+    r#"
+    CREATE INDEX verification_discord_roblox ON verification_discord (roblox_id);
+
+    CREATE TABLE verification_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        discord_id TEXT NOT NULL,
+        roblox_id INTEGER NOT NULL,
+        action TEXT NOT NULL,
+        time INTEGER NOT NULL,
+        api_key TEXT NOT NULL,
+        reason TEXT
+    );
+    CREATE INDEX verification_history_discord ON verification_history (discord_id);
+    CREATE INDEX verification_history_roblox ON verification_history (roblox_id);
+"#,
+    r#"
+    ALTER TABLE users ADD COLUMN inactivity_handled INTEGER;
+"#,
+    // Keeps the oldest link for each roblox account, the removed ones go to the history
+    // so they can be reviewed and relinked by hand
+    r#"
+    INSERT INTO verification_history (discord_id, roblox_id, action, time, api_key, reason)
+    SELECT discord_id, roblox_id, 'unlinked', CAST(strftime('%s', 'now') AS INTEGER) * 1000,
+           'migration', 'duplicate link to the same roblox account'
+    FROM verification_discord
+    WHERE rowid NOT IN (SELECT MIN(rowid) FROM verification_discord GROUP BY roblox_id);
+    DELETE FROM verification_discord
+    WHERE rowid NOT IN (SELECT MIN(rowid) FROM verification_discord GROUP BY roblox_id);
+
+    DROP INDEX verification_discord_roblox;
+    CREATE UNIQUE INDEX verification_discord_roblox ON verification_discord (roblox_id);
+"#,
+];
+
+const USER_COLUMNS: &str =
+    "user_id, name, points, total_points, events, floor_points, goal_points, \
+     rank, prestige, inactivity_handled";
+
+const LOA_COLUMNS: &str = "user_id, start_time, end_time, reason, granted_by";
+
+const EVENT_COLUMNS: &str =
+    "id, host_id, place_name, event_type, start_time, end_time, batch_id, api_key";
+
+const BP_LOG_COLUMNS: &str =
+    "user_id, time, awarder, amount, place_name, api_key, log_id, reverts, batch_id";
+
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        migrate(&conn)?;
+
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Writes many users and verifications in a single transaction, used by the firebase importer.
+    /// A second link to an already linked roblox account is skipped and recorded in the link
+    /// history instead, returns how many were skipped
+    pub fn import(
+        &self,
+        users: &[User],
+        awaiting: &HashMap<String, VerificationBody>,
+        verified: &[VerifiedStruct],
+    ) -> Result<usize, StorageError> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        for user in users {
+            write_user(&tx, user)?;
+        }
+        for (code, body) in awaiting {
+            write_awaiting(&tx, code, body)?;
+        }
+        let mut skipped = 0;
+        for verified_struct in verified {
+            match write_verified(&tx, verified_struct) {
+                Ok(()) => {}
+                Err(StorageError::Conflict(_)) => {
+                    write_link_record(
+                        &tx,
+                        &LinkRecord {
+                            discord_id: verified_struct.discord_id.clone(),
+                            roblox_id: verified_struct.roblox_id,
+                            action: LinkAction::Unlinked,
+                            time: SystemTime::now(),
+                            api_key: "import".to_string(),
+                            reason: Some("duplicate link to the same roblox account".to_string()),
+                        },
+                    )?;
+                    skipped += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        tx.commit()?;
+        Ok(skipped)
+    }
+}
+
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        conn.execute_batch(migration)?;
+        conn.pragma_update(None, "user_version", i + 1)?;
+    }
+
+    Ok(())
+}
+
+/// Division ranks are stored under their serde names so they round-trip with the firebase data
+fn division_to_sql<T: Serialize>(rank: Option<&T>) -> Option<String> {
+    rank.and_then(|r| serde_json::to_value(r).ok())
+        .and_then(|value| value.as_str().map(str::to_string))
+}
+
+fn division_from_sql<T: DeserializeOwned>(rank: Option<String>) -> Option<T> {
+    rank.and_then(|r| serde_json::from_value(serde_json::Value::String(r)).ok())
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+fn user_from_row(row: &Row) -> rusqlite::Result<User> {
+    Ok(User {
+        user_id: row.get(0)?,
+        name: row.get(1)?,
+        points: row.get(2)?,
+        total_points: row.get(3)?,
+        events: row.get(4)?,
+        floor_points: row.get(5)?,
+        goal_points: row.get(6)?,
+        rank: row.get(7)?,
+        divisions: None,
+        loa: None,
+        prestige: row.get(8)?,
+        bp_logs: None,
+        inactivity_handled: row.get(9)?,
+    })
+}
+
+fn divisions_from_row(row: &Row) -> rusqlite::Result<(u64, Divisions)> {
+    Ok((
+        row.get(0)?,
+        Divisions {
+            st: division_from_sql(row.get(1)?),
+            sable: division_from_sql(row.get(2)?),
+        },
+    ))
+}
+
+fn loa_from_row(row: &Row) -> rusqlite::Result<(u64, LeaveOfAbsence)> {
+    Ok((
+        row.get(0)?,
+        LeaveOfAbsence {
+            start_time: from_millis(row.get(1)?),
+            end_time: from_millis(row.get(2)?),
+            reason: row.get(3)?,
+            granted_by: row.get(4)?,
+        },
+    ))
+}
+
+fn event_from_row(row: &Row) -> rusqlite::Result<Event> {
+    Ok(Event {
+        id: row.get(0)?,
+        host_id: row.get(1)?,
+        place_name: row.get(2)?,
+        event_type: row.get(3)?,
+        start_time: from_millis(row.get(4)?),
+        end_time: row.get::<_, Option<i64>>(5)?.map(from_millis),
+        batch_id: row.get(6)?,
+        api_key: row.get(7)?,
+        attendees: vec![],
+    })
+}
+
+fn bp_log_from_row(row: &Row) -> rusqlite::Result<(u64, BPLog)> {
+    Ok((
+        row.get(0)?,
+        BPLog {
+            time: row.get(1)?,
+            awarder: row.get(2)?,
+            amount: row.get(3)?,
+            place_name: row.get(4)?,
+            api_key: row.get(5)?,
+            id: row.get(6)?,
+            reverts: row.get(7)?,
+            batch_id: row.get(8)?,
+        },
+    ))
+}
+
+/// Upserts the user, `OR REPLACE` would delete the row and cascade to the bP logs.
+/// bP logs are diffed by position: changed rows are updated, new ones inserted and
+/// any past the end of the list deleted, so the common append only touches one row
+fn write_user(conn: &Connection, user: &User) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO users ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT (user_id) DO UPDATE SET
+             name = excluded.name, points = excluded.points,
+             total_points = excluded.total_points, events = excluded.events,
+             floor_points = excluded.floor_points, goal_points = excluded.goal_points,
+             rank = excluded.rank, prestige = excluded.prestige,
+             inactivity_handled = excluded.inactivity_handled",
+            USER_COLUMNS
+        ),
+        params![
+            user.user_id,
+            user.name,
+            user.points,
+            user.total_points,
+            user.events,
+            user.floor_points,
+            user.goal_points,
+            user.rank,
+            user.prestige,
+            user.inactivity_handled,
+        ],
+    )?;
+
+    conn.execute("DELETE FROM divisions WHERE user_id = ?1", [user.user_id])?;
+    if let Some(divisions) = &user.divisions {
+        conn.execute(
+            "INSERT INTO divisions (user_id, st, sable) VALUES (?1, ?2, ?3)",
+            params![
+                user.user_id,
+                division_to_sql(divisions.st.as_ref()),
+                division_to_sql(divisions.sable.as_ref()),
+            ],
+        )?;
+    }
+
+    conn.execute("DELETE FROM leaves WHERE user_id = ?1", [user.user_id])?;
+    if let Some(loa) = &user.loa {
+        conn.execute(
+            "INSERT INTO leaves (user_id, start_time, end_time, reason, granted_by)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                user.user_id,
+                to_millis(loa.start_time),
+                to_millis(loa.end_time),
+                loa.reason,
+                loa.granted_by,
+            ],
+        )?;
+    }
+
+    let stored = read_bp_logs(conn, user.user_id)?;
+    let logs = user.bp_logs.as_deref().unwrap_or_default();
+    conn.execute(
+        "DELETE FROM bp_logs WHERE user_id = ?1 AND position >= ?2",
+        params![user.user_id, logs.len()],
+    )?;
+
+    let mut insert = conn.prepare(
+        "INSERT INTO bp_logs
+         (user_id, position, time, awarder, amount, place_name, api_key, log_id, reverts,
+          batch_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    )?;
+    let mut update = conn.prepare(
+        "UPDATE bp_logs SET time = ?3, awarder = ?4, amount = ?5, place_name = ?6,
+         api_key = ?7, log_id = ?8, reverts = ?9, batch_id = ?10
+         WHERE user_id = ?1 AND position = ?2",
+    )?;
+    for (position, log) in logs.iter().enumerate() {
+        let stmt = match stored.get(position) {
+            Some(existing) if existing == log => continue,
+            Some(_) => &mut update,
+            None => &mut insert,
+        };
+        stmt.execute(params![
+            user.user_id,
+            position,
+            log.time,
+            log.awarder,
+            log.amount,
+            log.place_name,
+            log.api_key,
+            log.id,
+            log.reverts,
+            log.batch_id,
+        ])?;
+    }
+
+    Ok(())
+}
+
+fn write_event(conn: &Connection, event: &Event) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO events ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            EVENT_COLUMNS
+        ),
+        params![
+            event.id,
+            event.host_id,
+            event.place_name,
+            event.event_type,
+            to_millis(event.start_time),
+            event.end_time.map(to_millis),
+            event.batch_id,
+            event.api_key,
+        ],
+    )?;
+
+    conn.execute(
+        "DELETE FROM event_attendees WHERE event_id = ?1",
+        [&event.id],
+    )?;
+    let mut stmt = conn.prepare(
+        "INSERT OR REPLACE INTO event_attendees (event_id, user_id, points) VALUES (?1, ?2, ?3)",
+    )?;
+    for attendee in event.attendees.iter() {
+        stmt.execute(params![event.id, attendee.user_id, attendee.points])?;
+    }
+
+    Ok(())
+}
+
+/// Loads events matching `filter` (a trailing SQL clause) along with their attendees
+fn read_events(
+    conn: &Connection,
+    filter: &str,
+    params: &[&dyn rusqlite::ToSql],
+) -> rusqlite::Result<Vec<Event>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM events {} ORDER BY start_time DESC",
+        EVENT_COLUMNS, filter
+    ))?;
+    let mut events = stmt
+        .query_map(params, event_from_row)?
+        .collect::<rusqlite::Result<Vec<Event>>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT user_id, points FROM event_attendees WHERE event_id = ?1 ORDER BY user_id",
+    )?;
+    for event in events.iter_mut() {
+        event.attendees = stmt
+            .query_map([&event.id], |row| {
+                Ok(Attendee {
+                    user_id: row.get(0)?,
+                    points: row.get(1)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+    }
+
+    Ok(events)
+}
+
+const AWAITING_COLUMNS: &str = "code, discord_id, creation_time, roblox_id, state";
+
+fn awaiting_from_row(row: &Row) -> rusqlite::Result<(String, VerificationBody)> {
+    let state: String = row.get(4)?;
+    Ok((
+        row.get(0)?,
+        VerificationBody {
+            discord_id: row.get(1)?,
+            creation_time: from_millis(row.get(2)?),
+            roblox_id: row.get(3)?,
+            state: VerificationState::from_name(&state).unwrap_or_default(),
+        },
+    ))
+}
+
+fn write_awaiting(conn: &Connection, code: &str, body: &VerificationBody) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO verification_awaiting ({}) VALUES (?1, ?2, ?3, ?4, ?5)",
+            AWAITING_COLUMNS
+        ),
+        params![
+            code,
+            body.discord_id,
+            to_millis(body.creation_time),
+            body.roblox_id,
+            body.state.to_string()
+        ],
+    )?;
+    Ok(())
+}
+
+/// Upserts on the discord id, `OR REPLACE` would silently drop another discord id's link
+/// instead of failing on the unique roblox id
+fn write_verified(conn: &Connection, verified: &VerifiedStruct) -> Result<(), StorageError> {
+    let result = conn.execute(
+        "INSERT INTO verification_discord (discord_id, roblox_id) VALUES (?1, ?2)
+         ON CONFLICT (discord_id) DO UPDATE SET roblox_id = excluded.roblox_id",
+        params![verified.discord_id, verified.roblox_id],
+    );
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::ConstraintViolation => {
+            Err(StorageError::Conflict(format!(
+                "roblox account {} is already linked to another discord id",
+                verified.roblox_id
+            )))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_link_record(conn: &Connection, record: &LinkRecord) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO verification_history (discord_id, roblox_id, action, time, api_key, reason)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            record.discord_id,
+            record.roblox_id,
+            record.action.to_string(),
+            to_millis(record.time),
+            record.api_key,
+            record.reason
+        ],
+    )?;
+    Ok(())
+}
+
+fn read_bp_logs(conn: &Connection, user_id: u64) -> rusqlite::Result<Vec<BPLog>> {
+    conn.prepare(&format!(
+        "SELECT {} FROM bp_logs WHERE user_id = ?1 ORDER BY position",
+        BP_LOG_COLUMNS
+    ))?
+    .query_map([user_id], bp_log_from_row)?
+    .map(|row| row.map(|(_, log)| log))
+    .collect()
+}
+
+/// Loads users matching `filter` (a trailing SQL clause) along with their divisions and leaves,
+/// and their bP logs when `with_logs` is set
+fn read_users(
+    conn: &Connection,
+    filter: &str,
+    params: &[&dyn rusqlite::ToSql],
+    with_logs: bool,
+) -> rusqlite::Result<Vec<User>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM users {}", USER_COLUMNS, filter))?;
+    let mut users = stmt
+        .query_map(params, user_from_row)?
+        .collect::<rusqlite::Result<Vec<User>>>()?;
+    if users.is_empty() {
+        return Ok(users);
+    }
+
+    // The filter may end in ORDER BY or LIMIT, so it's reused whole in a subquery
+    let selected = format!("WHERE user_id IN (SELECT user_id FROM users {})", filter);
+
+    let mut divisions: HashMap<u64, Divisions> = conn
+        .prepare(&format!(
+            "SELECT user_id, st, sable FROM divisions {}",
+            selected
+        ))?
+        .query_map(params, divisions_from_row)?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut leaves: HashMap<u64, LeaveOfAbsence> = conn
+        .prepare(&format!("SELECT {} FROM leaves {}", LOA_COLUMNS, selected))?
+        .query_map(params, loa_from_row)?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut logs: HashMap<u64, Vec<BPLog>> = HashMap::new();
+    if with_logs {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM bp_logs {} ORDER BY user_id, position",
+            BP_LOG_COLUMNS, selected
+        ))?;
+        for row in stmt.query_map(params, bp_log_from_row)? {
+            let (user_id, log) = row?;
+            logs.entry(user_id).or_default().push(log);
+        }
+    }
+
+    for user in users.iter_mut() {
+        user.divisions = divisions.remove(&user.user_id);
+        user.loa = leaves.remove(&user.user_id);
+        user.bp_logs = logs.remove(&user.user_id);
+    }
+
+    Ok(users)
+}
+
+fn read_user(conn: &Connection, user_id: u64) -> rusqlite::Result<Option<User>> {
+    let mut user = match conn
+        .query_row(
+            &format!("SELECT {} FROM users WHERE user_id = ?1", USER_COLUMNS),
+            [user_id],
+            user_from_row,
+        )
+        .optional()?
+    {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+
+    user.divisions = conn
+        .query_row(
+            "SELECT user_id, st, sable FROM divisions WHERE user_id = ?1",
+            [user_id],
+            divisions_from_row,
+        )
+        .optional()?
+        .map(|(_, divisions)| divisions);
+
+    user.loa = conn
+        .query_row(
+            &format!("SELECT {} FROM leaves WHERE user_id = ?1", LOA_COLUMNS),
+            [user_id],
+            loa_from_row,
+        )
+        .optional()?
+        .map(|(_, loa)| loa);
+
+    let logs = read_bp_logs(conn, user_id)?;
+    if !logs.is_empty() {
+        user.bp_logs = Some(logs);
+    }
+
+    Ok(Some(user))
+}
+
+#[async_trait]
+impl UserStore for SqliteStore {
+    async fn get_user(&self, user_id: u64) -> Result<Option<User>, StorageError> {
+        Ok(read_user(&self.conn.lock(), user_id)?)
+    }
+
+    async fn put_user(&self, user: &User) -> Result<(), StorageError> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        write_user(&tx, user)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn get_users(&self) -> Result<Vec<User>, StorageError> {
+        Ok(read_users(&self.conn.lock(), "", &[], true)?)
+    }
+
+    /// Served straight off the `users_points` index, the leaderboard doesn't need the bP logs
+    async fn get_users_by_points(&self) -> Result<Vec<User>, StorageError> {
+        Ok(read_users(
+            &self.conn.lock(),
+            "ORDER BY points DESC",
+            &[],
+            false,
+        )?)
+    }
+
+    async fn get_users_by_batch(&self, batch_id: &str) -> Result<Vec<User>, StorageError> {
+        Ok(read_users(
+            &self.conn.lock(),
+            "WHERE user_id IN (SELECT user_id FROM bp_logs WHERE batch_id = ?1)",
+            &[&batch_id],
+            true,
+        )?)
+    }
+
+    async fn list_users(
+        &self,
+        filter: &UserFilter,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<User>, StorageError> {
+        let mut clauses: Vec<String> = vec![];
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+
+        if let Some(after) = after {
+            params.push(Box::new(after));
+            clauses.push(format!("user_id > ?{}", params.len()));
+        }
+        if let Some(rank) = &filter.rank {
+            params.push(Box::new(rank.clone()));
+            clauses.push(format!("rank = ?{}", params.len()));
+        }
+        if let Some(division) = filter.division {
+            let column = match division {
+                Division::St => "st",
+                Division::Sable => "sable",
+            };
+            clauses.push(format!(
+                "user_id IN (SELECT user_id FROM divisions WHERE {} IS NOT NULL)",
+                column
+            ));
+        }
+        if let Some(min_points) = filter.min_points {
+            params.push(Box::new(min_points));
+            clauses.push(format!("points >= ?{}", params.len()));
+        }
+        if let Some(prefix) = &filter.name_prefix {
+            let escaped = prefix
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            params.push(Box::new(format!("{}%", escaped)));
+            clauses.push(format!("name LIKE ?{} ESCAPE '\\'", params.len()));
+        }
+
+        let mut sql = String::new();
+        if !clauses.is_empty() {
+            sql = format!("WHERE {} ", clauses.join(" AND "));
+        }
+        params.push(Box::new(limit as i64));
+        sql.push_str(&format!("ORDER BY user_id LIMIT ?{}", params.len()));
+
+        let params: Vec<&dyn rusqlite::ToSql> = params.iter().map(|param| param.as_ref()).collect();
+        Ok(read_users(&self.conn.lock(), &sql, &params, true)?)
+    }
+
+    async fn find_user_by_name(&self, name: &str) -> Result<Option<User>, StorageError> {
+        Ok(read_users(
+            &self.conn.lock(),
+            "WHERE name = ?1 COLLATE NOCASE LIMIT 1",
+            &[&name],
+            true,
+        )?
+        .pop())
+    }
+}
+
+#[async_trait]
+impl VerificationStore for SqliteStore {
+    async fn get_awaiting(&self, code: &str) -> Result<Option<VerificationBody>, StorageError> {
+        Ok(self
+            .conn
+            .lock()
+            .query_row(
+                &format!(
+                    "SELECT {} FROM verification_awaiting WHERE code = ?1",
+                    AWAITING_COLUMNS
+                ),
+                [code],
+                awaiting_from_row,
+            )
+            .optional()?
+            .map(|(_, body)| body))
+    }
+
+    async fn get_awaiting_map(&self) -> Result<HashMap<String, VerificationBody>, StorageError> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM verification_awaiting",
+            AWAITING_COLUMNS
+        ))?;
+        let map = stmt
+            .query_map([], awaiting_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(map)
+    }
+
+    async fn put_awaiting(&self, code: &str, body: &VerificationBody) -> Result<(), StorageError> {
+        Ok(write_awaiting(&self.conn.lock(), code, body)?)
+    }
+
+    async fn delete_awaiting(&self, code: &str) -> Result<(), StorageError> {
+        self.conn
+            .lock()
+            .execute("DELETE FROM verification_awaiting WHERE code = ?1", [code])?;
+        Ok(())
+    }
+
+    async fn get_verified(&self, discord_id: &str) -> Result<Option<VerifiedStruct>, StorageError> {
+        Ok(self
+            .conn
+            .lock()
+            .query_row(
+                "SELECT discord_id, roblox_id FROM verification_discord WHERE discord_id = ?1",
+                [discord_id],
+                |row| {
+                    Ok(VerifiedStruct {
+                        discord_id: row.get(0)?,
+                        roblox_id: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    async fn put_verified(&self, verified: &VerifiedStruct) -> Result<(), StorageError> {
+        write_verified(&self.conn.lock(), verified)
+    }
+
+    async fn delete_verified(&self, discord_id: &str) -> Result<(), StorageError> {
+        self.conn.lock().execute(
+            "DELETE FROM verification_discord WHERE discord_id = ?1",
+            [discord_id],
+        )?;
+        Ok(())
+    }
+
+    async fn get_verified_by_roblox(
+        &self,
+        roblox_id: u64,
+    ) -> Result<Option<VerifiedStruct>, StorageError> {
+        Ok(self
+            .conn
+            .lock()
+            .query_row(
+                "SELECT discord_id, roblox_id FROM verification_discord WHERE roblox_id = ?1
+                 LIMIT 1",
+                [roblox_id],
+                |row| {
+                    Ok(VerifiedStruct {
+                        discord_id: row.get(0)?,
+                        roblox_id: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    async fn add_link_record(&self, record: &LinkRecord) -> Result<(), StorageError> {
+        Ok(write_link_record(&self.conn.lock(), record)?)
+    }
+
+    async fn get_link_records(&self) -> Result<Vec<LinkRecord>, StorageError> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            "SELECT discord_id, roblox_id, action, time, api_key, reason
+             FROM verification_history ORDER BY id",
+        )?;
+        let records = stmt
+            .query_map([], |row| {
+                let action: String = row.get(2)?;
+                Ok(LinkRecord {
+                    discord_id: row.get(0)?,
+                    roblox_id: row.get(1)?,
+                    action: LinkAction::from_name(&action).unwrap_or(LinkAction::Linked),
+                    time: from_millis(row.get(3)?),
+                    api_key: row.get(4)?,
+                    reason: row.get(5)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl EventStore for SqliteStore {
+    async fn get_event(&self, event_id: &str) -> Result<Option<Event>, StorageError> {
+        let events = read_events(&self.conn.lock(), "WHERE id = ?1", &[&event_id])?;
+        Ok(events.into_iter().next())
+    }
+
+    async fn put_event(&self, event: &Event) -> Result<(), StorageError> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        write_event(&tx, event)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn delete_event(&self, event_id: &str) -> Result<(), StorageError> {
+        // attendees go with it through the foreign key
+        self.conn
+            .lock()
+            .execute("DELETE FROM events WHERE id = ?1", [event_id])?;
+        Ok(())
+    }
+
+    async fn remove_attendee(&self, event_id: &str, user_id: u64) -> Result<(), StorageError> {
+        self.conn.lock().execute(
+            "DELETE FROM event_attendees WHERE event_id = ?1 AND user_id = ?2",
+            params![event_id, user_id],
+        )?;
+        Ok(())
+    }
+
+    async fn get_events(&self) -> Result<Vec<Event>, StorageError> {
+        Ok(read_events(&self.conn.lock(), "", &[])?)
+    }
+
+    async fn get_user_events(&self, user_id: u64) -> Result<Vec<Event>, StorageError> {
+        Ok(read_events(
+            &self.conn.lock(),
+            "WHERE id IN (SELECT event_id FROM event_attendees WHERE user_id = ?1)",
+            &[&user_id],
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        definitions::ranks::STRanks, storage::MemoryStore, test_support::user as test_user,
+    };
+
+    /// Deletes the database file when the test ends
+    struct TempDb(std::path::PathBuf);
+
+    impl TempDb {
+        fn new() -> Self {
+            TempDb(std::env::temp_dir().join(format!(
+                "wave-mainframe-test-{:016x}.db",
+                rand::random::<u64>()
+            )))
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{}{}", self.0.display(), suffix));
+            }
+        }
+    }
+
+    fn user() -> User {
+        let mut user = test_user("Corporal", 120);
+        user.total_points = 130;
+        user.divisions = Some(Divisions {
+            st: Some(STRanks::Chairman),
+            sable: None,
+        });
+        user.loa = Some(LeaveOfAbsence {
+            start_time: UNIX_EPOCH + Duration::from_millis(1_700_000_000_000),
+            end_time: UNIX_EPOCH + Duration::from_millis(1_700_600_000_000),
+            reason: Some("exams".to_string()),
+            granted_by: 9,
+        });
+        user.bp_logs = Some(vec![
+            BPLog::new(7, 100, "test"),
+            BPLog::new(7, 30, "test"),
+            BPLog::new(8, -10, "test"),
+        ]);
+        user
+    }
+
+    /// Writes `user` to both stores and checks they read it back the same
+    async fn assert_round_trip(sqlite: &SqliteStore, memory: &MemoryStore, user: &User) {
+        sqlite.put_user(user).await.unwrap();
+        memory.put_user(user).await.unwrap();
+
+        let from_sqlite = sqlite.get_user(user.user_id).await.unwrap();
+        let from_memory = memory.get_user(user.user_id).await.unwrap();
+        assert_eq!(
+            serde_json::to_value(&from_sqlite).unwrap(),
+            serde_json::to_value(&from_memory).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&from_sqlite).unwrap(),
+            serde_json::to_value(Some(user)).unwrap()
+        );
+    }
+
+    #[test]
+    fn migrations_run_once_to_the_latest_version() {
+        let db = TempDb::new();
+        let version = |store: &SqliteStore| -> usize {
+            store
+                .conn
+                .lock()
+                .query_row("PRAGMA user_version", [], |row| row.get(0))
+                .unwrap()
+        };
+
+        let store = SqliteStore::open(&db.0).unwrap();
+        assert_eq!(version(&store), MIGRATIONS.len());
+        drop(store);
+
+        let store = SqliteStore::open(&db.0).unwrap();
+        assert_eq!(version(&store), MIGRATIONS.len());
+    }
+
+    #[tokio::test]
+    async fn user_round_trips_like_the_memory_store() {
+        let db = TempDb::new();
+        let sqlite = SqliteStore::open(&db.0).unwrap();
+        let memory = MemoryStore::new();
+        let mut user = user();
+
+        assert_round_trip(&sqlite, &memory, &user).await;
+
+        // append, edit and remove logs, then drop them all
+        let logs = user.bp_logs.as_mut().unwrap();
+        logs.push(BPLog::new(7, 5, "test"));
+        logs[0].amount = 90;
+        logs.remove(1);
+        assert_round_trip(&sqlite, &memory, &user).await;
+
+        user.bp_logs = None;
+        user.loa = None;
+        user.divisions = None;
+        assert_round_trip(&sqlite, &memory, &user).await;
+    }
+
+    #[tokio::test]
+    async fn leaderboard_is_sorted_without_logs() {
+        let db = TempDb::new();
+        let sqlite = SqliteStore::open(&db.0).unwrap();
+        for (user_id, points) in [(1, 50), (2, 300), (3, 120)] {
+            let mut user = user();
+            user.user_id = user_id;
+            user.points = points;
+            sqlite.put_user(&user).await.unwrap();
+        }
+
+        let users = sqlite.get_users_by_points().await.unwrap();
+
+        let order: Vec<u64> = users.iter().map(|user| user.user_id).collect();
+        assert_eq!(order, [2, 3, 1]);
+        assert!(users.iter().all(|user| user.bp_logs.is_none()));
+        assert!(users.iter().all(|user| user.loa.is_some()));
+    }
+
+    #[tokio::test]
+    async fn roblox_account_links_to_one_discord_id() {
+        let db = TempDb::new();
+        let sqlite = SqliteStore::open(&db.0).unwrap();
+        let link = |discord_id: &str| VerifiedStruct {
+            roblox_id: 1,
+            discord_id: discord_id.to_string(),
+        };
+
+        sqlite.put_verified(&link("a")).await.unwrap();
+        // relinking the same discord id is an update, not a second link
+        sqlite.put_verified(&link("a")).await.unwrap();
+
+        assert!(matches!(
+            sqlite.put_verified(&link("b")).await,
+            Err(StorageError::Conflict(_))
+        ));
+    }
+}
@@ -0,0 +1,77 @@
+//! Fixtures shared by the unit tests
+
+use crate::{
+    config::PrestigeConfig,
+    definitions::{
+        ranks::{RankDefinition, RankLadder},
+        users::User,
+    },
+};
+
+pub fn user(rank: &str, points: i32) -> User {
+    User {
+        user_id: 1,
+        name: "tester".to_string(),
+        points,
+        total_points: 0,
+        events: 0,
+        floor_points: None,
+        goal_points: None,
+        rank: rank.to_string(),
+        divisions: None,
+        prestige: None,
+        bp_logs: None,
+        loa: None,
+        inactivity_handled: None,
+    }
+}
+
+pub fn rank(
+    name: &str,
+    value: u64,
+    required_points: Option<i32>,
+    next: Option<&str>,
+    prev: Option<&str>,
+) -> RankDefinition {
+    RankDefinition {
+        name: name.to_string(),
+        value,
+        role_id: value * 100,
+        required_points,
+        next: next.map(str::to_string),
+        prev: prev.map(str::to_string),
+        promotable: required_points.is_some(),
+    }
+}
+
+/// Enlisted (0) → Corporal (100) → Sergeant (300) → Officer (manual only)
+pub fn ranks() -> Vec<RankDefinition> {
+    vec![
+        rank("Officer", 20, None, None, Some("Sergeant")),
+        rank("Sergeant", 15, Some(300), Some("Officer"), Some("Corporal")),
+        rank(
+            "Corporal",
+            10,
+            Some(100),
+            Some("Sergeant"),
+            Some("Enlisted"),
+        ),
+        rank("Enlisted", 5, Some(0), Some("Corporal"), None),
+    ]
+}
+
+pub fn ladder() -> RankLadder {
+    RankLadder::new(ranks(), "Enlisted".to_string()).unwrap()
+}
+
+/// Prestige from Sergeant at 300 bP back to Enlisted
+pub fn prestige() -> PrestigeConfig {
+    PrestigeConfig {
+        rank: "Sergeant".to_string(),
+        required_points: 300,
+        reset_points: 0,
+        reset_rank: Some("Enlisted".to_string()),
+        badge_group: None,
+        badge_role_id: None,
+    }
+}